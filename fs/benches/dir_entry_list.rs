@@ -0,0 +1,101 @@
+//! Compares `DirEntryList` with the `Vec` dirs used to keep their entries in. Run with
+//! `cargo bench` on the host.
+
+use fs::{DirEntry, DirEntryList, File, FileKind, RegularFile};
+use parking_lot::RwLock;
+use std::hint::black_box;
+use std::sync::Arc;
+use std::time::Instant;
+
+const ITERS: u32 = 100;
+
+fn main() {
+    for &n in &[10_000, 100_000] {
+        bench(&format!("lookup_vec_{}k", n / 1000), bench_lookup_vec(n));
+        bench(&format!("lookup_{}k", n / 1000), bench_lookup(n));
+        bench(&format!("remove_vec_{}k", n / 1000), bench_remove_vec(n));
+        bench(&format!("remove_{}k", n / 1000), bench_remove(n));
+    }
+}
+
+fn bench(name: &str, mut f: impl FnMut()) {
+    f();
+    let start = Instant::now();
+    for _ in 0..ITERS {
+        f();
+    }
+    println!("{:<16} {:>12?}/iter", name, start.elapsed() / ITERS);
+}
+
+fn names(n: usize) -> Vec<String> {
+    (0..n).map(|i| format!("tile_{:06}.png", i)).collect()
+}
+
+fn entries(names: &[String]) -> Vec<DirEntry> {
+    let file = Arc::new(RwLock::new(File::new(FileKind::RegularFile(
+        RegularFile::Buf(Vec::new()),
+    ))));
+    names
+        .iter()
+        .map(|name| DirEntry::new(name.clone(), file.clone()))
+        .collect()
+}
+
+fn list(names: &[String]) -> DirEntryList {
+    let mut list = DirEntryList::new();
+    for ent in entries(names) {
+        list.push(ent);
+    }
+    list
+}
+
+/// Looks up every 100th name, which is what the dirs used to do with a `Vec`.
+fn bench_lookup_vec(n: usize) -> impl FnMut() {
+    let names = names(n);
+    let ents = entries(&names);
+    move || {
+        for name in names.iter().step_by(100) {
+            black_box(ents.iter().find(|ent| &ent.name == name));
+        }
+    }
+}
+
+fn bench_lookup(n: usize) -> impl FnMut() {
+    let names = names(n);
+    let list = list(&names);
+    move || {
+        for name in names.iter().step_by(100) {
+            black_box(list.get(name));
+        }
+    }
+}
+
+/// Removes an entry from the middle and adds it back, like renaming it in place.
+fn bench_remove_vec(n: usize) -> impl FnMut() {
+    let names = names(n);
+    let mut ents = entries(&names);
+    move || {
+        let name = &names[n / 2];
+        let pos = ents.iter().position(|ent| &ent.name == name).unwrap();
+        let ent = ents.remove(pos);
+        ents.push(ent);
+    }
+}
+
+fn bench_remove(n: usize) -> impl FnMut() {
+    let names = names(n);
+    let mut list = list(&names);
+    move || {
+        let ent = list.remove(&names[n / 2]).unwrap();
+        list.push(ent);
+    }
+}
+
+// What the host provides the file system when it runs as WebAssembly.
+#[no_mangle]
+extern "C" fn now() -> u64 {
+    0
+}
+
+#[no_mangle]
+extern "C" fn url_free(_url_ptr: *const u8, _url_len: usize) {}
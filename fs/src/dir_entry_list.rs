@@ -0,0 +1,106 @@
+use crate::*;
+use std::collections::{BTreeMap, HashMap};
+
+// `fd_readdir` lists `.` and `..` first, as cookies 0 and 1.
+const FIRST_DIR_ENTRY_COOKIE: u64 = 2;
+
+/// The entries of a dir, listed in the order they were added and looked up by name.
+/// Dirs can hold hundreds of thousands of files, so neither should have to go through
+/// all of them.
+pub struct DirEntryList {
+    /// Keyed by cookie. Entries get increasing cookies in the order they're added, and
+    /// keep them until they're removed. That way a `fd_readdir` that resumes from a
+    /// cookie neither skips nor repeats entries, whatever changed in between.
+    by_cookie: BTreeMap<u64, DirEntry>,
+    by_name: HashMap<String, u64>,
+    next_cookie: u64,
+    /// The dir holding this one and its name there, which `Dir::push` keeps up to date.
+    /// Unset for the root dir.
+    parent: Option<(DirEntriesKey, String)>,
+}
+
+impl Default for DirEntryList {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl DirEntryList {
+    pub fn new() -> Self {
+        Self {
+            by_cookie: BTreeMap::new(),
+            by_name: HashMap::new(),
+            next_cookie: FIRST_DIR_ENTRY_COOKIE,
+            parent: None,
+        }
+    }
+
+    pub fn parent(&self) -> Option<DirEntriesKey> {
+        self.parent.as_ref().map(|(parent, _)| *parent)
+    }
+
+    pub fn set_parent(&mut self, parent: DirEntriesKey, name: String) {
+        self.parent = Some((parent, name));
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.by_cookie.is_empty()
+    }
+
+    pub fn get(&self, name: &str) -> Option<&DirEntry> {
+        let cookie = self.by_name.get(name)?;
+        self.by_cookie.get(cookie)
+    }
+
+    /// Adds `ent` after the entries already there, replacing any with the same name.
+    pub fn push(&mut self, mut ent: DirEntry) {
+        self.remove(&ent.name);
+        ent.cookie = self.next_cookie;
+        self.next_cookie += 1;
+        self.by_name.insert(ent.name.clone(), ent.cookie);
+        self.by_cookie.insert(ent.cookie, ent);
+    }
+
+    pub fn remove(&mut self, name: &str) -> Option<DirEntry> {
+        let cookie = self.by_name.remove(name)?;
+        self.by_cookie.remove(&cookie)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &DirEntry> {
+        self.by_cookie.values()
+    }
+
+    /// The entries from the one with `cookie` on (or the next one if it's gone).
+    pub fn iter_from(&self, cookie: u64) -> impl Iterator<Item = &DirEntry> {
+        self.by_cookie.range(cookie..).map(|(_, ent)| ent)
+    }
+}
+
+/// The entry for the dir whose entries are `entries`, found through its parent (the root
+/// dir has `ROOT_DIR_ENTRY`). Fails with ENOENT once the dir is removed.
+pub fn dir_entry(entries: DirEntriesKey, ents: &DirEntries) -> Result<&DirEntry> {
+    if entries == ROOT_DIR_ENTRIES {
+        return Ok(&ROOT_DIR_ENTRY);
+    }
+    let (parent, name) = ents
+        .get(&entries)
+        .and_then(|list| list.parent.as_ref())
+        .ok_or(ERRNO_NOENT)?;
+    match ents.get(parent).and_then(|list| list.get(name)) {
+        Some(ent) if ent.entries == Some(entries) => Ok(ent),
+        _ => Err(ERRNO_NOENT),
+    }
+}
+
+/// The path of the dir whose entries are `entries`, relative to the root dir. It's found
+/// by going up through the parents, so it's wherever the dir is now rather than where it
+/// was opened. Fails with ENOENT once the dir (or one above it) is removed.
+pub fn dir_path(mut entries: DirEntriesKey, ents: &DirEntries) -> Result<String> {
+    let mut names = vec![];
+    while entries != ROOT_DIR_ENTRIES {
+        names.push(dir_entry(entries, ents)?.name.as_str());
+        entries = ents[&entries].parent().unwrap();
+    }
+    names.reverse();
+    Ok(names.join("/"))
+}
@@ -4,22 +4,112 @@ use std::collections::HashMap;
 use std::sync::Arc;
 use wasi::*;
 
-#[derive(Default)]
+// Rights that apply to regular files and devices.
+pub const RIGHTS_FILE: Rights = RIGHTS_FD_DATASYNC
+    | RIGHTS_FD_READ
+    | RIGHTS_FD_SEEK
+    | RIGHTS_FD_FDSTAT_SET_FLAGS
+    | RIGHTS_FD_SYNC
+    | RIGHTS_FD_TELL
+    | RIGHTS_FD_WRITE
+    | RIGHTS_FD_ADVISE
+    | RIGHTS_FD_ALLOCATE
+    | RIGHTS_FD_FILESTAT_GET
+    | RIGHTS_FD_FILESTAT_SET_SIZE
+    | RIGHTS_FD_FILESTAT_SET_TIMES
+    | RIGHTS_POLL_FD_READWRITE;
+// Rights that apply to dirs.
+pub const RIGHTS_DIR: Rights = RIGHTS_FD_FDSTAT_SET_FLAGS
+    | RIGHTS_FD_SEEK
+    | RIGHTS_FD_SYNC
+    | RIGHTS_FD_TELL
+    | RIGHTS_FD_ADVISE
+    | RIGHTS_PATH_CREATE_DIRECTORY
+    | RIGHTS_PATH_CREATE_FILE
+    | RIGHTS_PATH_LINK_SOURCE
+    | RIGHTS_PATH_LINK_TARGET
+    | RIGHTS_PATH_OPEN
+    | RIGHTS_FD_READDIR
+    | RIGHTS_PATH_READLINK
+    | RIGHTS_PATH_RENAME_SOURCE
+    | RIGHTS_PATH_RENAME_TARGET
+    | RIGHTS_PATH_FILESTAT_GET
+    | RIGHTS_PATH_FILESTAT_SET_SIZE
+    | RIGHTS_PATH_FILESTAT_SET_TIMES
+    | RIGHTS_FD_FILESTAT_GET
+    | RIGHTS_FD_FILESTAT_SET_TIMES
+    | RIGHTS_PATH_SYMLINK
+    | RIGHTS_PATH_REMOVE_DIRECTORY
+    | RIGHTS_PATH_UNLINK_FILE
+    | RIGHTS_POLL_FD_READWRITE;
+pub const FDFLAGS_ALL: Fdflags =
+    FDFLAGS_APPEND | FDFLAGS_DSYNC | FDFLAGS_NONBLOCK | FDFLAGS_RSYNC | FDFLAGS_SYNC;
+// Same as Linux's default soft limit.
+pub const DEFAULT_MAX_OPEN_FILES: usize = 1024;
+
 pub struct FDTable {
     map: HashMap<Fd, Arc<RwLock<FileDesc>>>,
+    max_open_files: usize,
 }
 
 impl FDTable {
-    pub fn init() -> Self {
-        let mut this = Self::default();
-        for _ in 0..3 {
-            let file = Arc::new(RwLock::new(File::RegularFile(RegularFile::Buf(Vec::new()))));
-            this.open(file, 0);
+    /// Opens the `stdio` pipes (for reading stdin and writing the others), and `dir` (the
+    /// process' cwd) as the `/` preopen.
+    pub fn init(dir: Arc<RwLock<File>>, stdio: &[Fifo; 3]) -> Self {
+        let mut this = Self {
+            map: HashMap::new(),
+            max_open_files: DEFAULT_MAX_OPEN_FILES,
+        };
+        for (fd, pipe) in stdio.iter().enumerate() {
+            let rights = if fd == 0 {
+                RIGHTS_FILE & !RIGHTS_FD_WRITE
+            } else {
+                RIGHTS_FILE & !RIGHTS_FD_READ
+            };
+            let file = Arc::new(RwLock::new(File::new(FileKind::Fifo(pipe.clone()))));
+            this.open(file, ROOT_DIR_ENTRIES, RootEscape::Deny, 0, rights, 0)
+                .unwrap();
         }
-        this.preopen(ROOT_DIR.clone(), "/".into());
+        this.preopen(dir, "/".into(), !0, RootEscape::Deny).unwrap();
         this
     }
 
+    /// The open descriptors, in order.
+    pub fn fds(&self) -> Vec<Fd> {
+        let mut fds = self.map.keys().copied().collect::<Vec<_>>();
+        fds.sort_unstable();
+        fds
+    }
+
+    pub fn max_open_files(&self) -> usize {
+        self.max_open_files
+    }
+
+    /// Only affects later opens, like lowering `RLIMIT_NOFILE`.
+    pub fn set_max_open_files(&mut self, max: usize) {
+        self.max_open_files = max;
+    }
+
+    /// Like POSIX, the lowest descriptor that isn't open.
+    fn next_fd(&self) -> Result<Fd> {
+        if self.map.len() >= self.max_open_files {
+            return Err(ERRNO_MFILE);
+        }
+        // There are at most `map.len()` taken descriptors, so this finds one.
+        Ok((0..).find(|fd| !self.map.contains_key(fd)).unwrap())
+    }
+
+    /// The preopened descriptors and their names, in order.
+    pub fn preopens(&self) -> Vec<(Fd, String)> {
+        let mut preopens = self
+            .map
+            .iter()
+            .filter_map(|(fd, desc)| desc.read().preopen.clone().map(|name| (*fd, name)))
+            .collect::<Vec<_>>();
+        preopens.sort_unstable();
+        preopens
+    }
+
     pub fn get(&self, fd: Fd) -> Result<&Arc<RwLock<FileDesc>>> {
         if let Some(desc) = self.map.get(&fd) {
             Ok(desc)
@@ -28,25 +118,78 @@ impl FDTable {
         }
     }
 
-    pub fn preopen(&mut self, file: Arc<RwLock<File>>, preopen: String) -> Fd {
-        let fd = next_fd();
-        self.map.insert(
-            fd,
-            Arc::new(RwLock::new(FileDesc::new(file, 0, Some(preopen)))),
+    /// Like `get`, but `/dev/stdin`, `/dev/stdout` and `/dev/stderr` are swapped for the
+    /// descriptor they stand for.
+    pub fn get_io(&self, fd: Fd) -> Result<&Arc<RwLock<FileDesc>>> {
+        let desc = self.get(fd)?;
+        let target = desc
+            .read()
+            .file
+            .read()
+            .as_char_device()
+            .and_then(|dev| dev.fd());
+        if let Some(desc) = target.and_then(|target| self.map.get(&target)) {
+            return Ok(desc);
+        }
+        Ok(desc)
+    }
+
+    /// Opens the dir `file` for the guest to find as `preopen`. Paths resolved through it
+    /// stay within it, and only `rights` are granted to it and what's opened through it.
+    pub fn preopen(
+        &mut self,
+        file: Arc<RwLock<File>>,
+        preopen: String,
+        rights: Rights,
+        escape: RootEscape,
+    ) -> Result<Fd> {
+        let root = file.read().as_dir()?.key();
+        let fd = self.next_fd()?;
+        let mut desc = FileDesc::new(
+            file,
+            0,
+            Some(preopen),
+            RIGHTS_DIR & rights,
+            (RIGHTS_DIR | RIGHTS_FILE) & rights,
         );
-        fd
+        desc.root = root;
+        desc.escape = escape;
+        self.map.insert(fd, Arc::new(RwLock::new(desc)));
+        Ok(fd)
     }
 
-    pub fn open(&mut self, file: Arc<RwLock<File>>, flags: Fdflags) -> Fd {
-        let fd = next_fd();
+    /// `root` and `escape` are only needed for dirs, see `FileDesc::resolve`.
+    pub fn open(
+        &mut self,
+        file: Arc<RwLock<File>>,
+        root: DirEntriesKey,
+        escape: RootEscape,
+        flags: Fdflags,
+        rights: Rights,
+        rights_inheriting: Rights,
+    ) -> Result<Fd> {
+        let fd = self.next_fd()?;
         let pos = if (flags & FDFLAGS_APPEND) != 0 {
             file.read().size()
         } else {
             0
         };
-        self.map
-            .insert(fd, Arc::new(RwLock::new(FileDesc::new(file, pos, None))));
-        fd
+        let mut desc = FileDesc::new(file, pos, None, rights, rights_inheriting);
+        desc.root = root;
+        desc.escape = escape;
+        desc.flags = flags & FDFLAGS_ALL;
+        desc.fifo = desc
+            .file
+            .read()
+            .as_fifo()
+            .map(|fifo| fifo.open((desc.access & R_OK) != 0, (desc.access & W_OK) != 0));
+        self.map.insert(fd, Arc::new(RwLock::new(desc)));
+        Ok(fd)
+    }
+
+    /// Closes every descriptor, preopens included, like exiting does.
+    pub fn close_all(&mut self) {
+        self.map.clear();
     }
 
     pub fn close(&mut self, fd: Fd) -> Result<()> {
@@ -57,14 +200,98 @@ impl FDTable {
         }
     }
 
+    /// Closes `to` and moves `from` into its place, like WASI's `fd_renumber`.
     pub fn renumber(&mut self, from: Fd, to: Fd) -> Result<()> {
-        let from_desc = self.get(from)?.clone();
-        let to_desc = self.get(to)?.clone();
-        if from_desc.read().preopen.is_some() || to_desc.read().preopen.is_some() {
+        // One at a time, since both can be the same descriptor and it's not reentrant.
+        let is_preopen = |desc: &Arc<RwLock<FileDesc>>| desc.read().preopen.is_some();
+        if is_preopen(self.get(from)?) || is_preopen(self.get(to)?) {
             return Err(ERRNO_BADF);
         }
-        self.map.insert(from, to_desc);
-        self.map.insert(to, from_desc);
+        if from != to {
+            let desc = self.map.remove(&from).unwrap();
+            self.map.insert(to, desc);
+        }
         Ok(())
     }
+
+    /// Opens the lowest free descriptor onto the same open file description as `fd`, so
+    /// they share the position and flags.
+    pub fn dup(&mut self, fd: Fd) -> Result<Fd> {
+        let desc = self.get(fd)?.clone();
+        let new_fd = self.next_fd()?;
+        self.map.insert(new_fd, desc);
+        Ok(new_fd)
+    }
+
+    /// Like `dup`, but onto `to`, which is closed first if it's open.
+    pub fn dup2(&mut self, from: Fd, to: Fd) -> Result<Fd> {
+        let desc = self.get(from)?.clone();
+        if from == to {
+            return Ok(to);
+        }
+        if to as usize >= self.max_open_files {
+            return Err(ERRNO_BADF);
+        }
+        match self.map.get(&to) {
+            Some(to_desc) if to_desc.read().preopen.is_some() => return Err(ERRNO_BADF),
+            Some(_) => {}
+            // Like `next_fd`, there can be descriptors past a lowered limit.
+            None if self.map.len() >= self.max_open_files => return Err(ERRNO_MFILE),
+            None => {}
+        }
+        self.map.insert(to, desc);
+        Ok(to)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn table(max_open_files: usize) -> FDTable {
+        FDTable {
+            map: HashMap::new(),
+            max_open_files,
+        }
+    }
+
+    fn open(fds: &mut FDTable) -> Result<Fd> {
+        let file = File::new(FileKind::RegularFile(RegularFile::Buf(Vec::new())));
+        fds.open(
+            Arc::new(RwLock::new(file)),
+            ROOT_DIR_ENTRIES,
+            RootEscape::Deny,
+            0,
+            RIGHTS_FILE,
+            0,
+        )
+    }
+
+    #[test]
+    fn open_past_limit() {
+        let mut fds = table(2);
+        assert_eq!(open(&mut fds), Ok(0));
+        assert_eq!(open(&mut fds), Ok(1));
+        assert_eq!(open(&mut fds), Err(ERRNO_MFILE));
+        fds.close(0).unwrap();
+        assert_eq!(open(&mut fds), Ok(0));
+    }
+
+    #[test]
+    fn dup2_past_limit() {
+        let mut fds = table(4);
+        for _ in 0..3 {
+            open(&mut fds).unwrap();
+        }
+        assert_eq!(fds.dup2(0, 3), Ok(3));
+        assert_eq!(fds.dup2(0, 4), Err(ERRNO_BADF));
+
+        // With 3 open and a limit of 3, opening another fails even below the limit...
+        fds.close(1).unwrap();
+        fds.set_max_open_files(3);
+        assert_eq!(fds.dup2(0, 1), Err(ERRNO_MFILE));
+        assert_eq!(fds.fds(), [0, 2, 3]);
+        // ...but replacing one that's open doesn't grow the table.
+        assert_eq!(fds.dup2(0, 2), Ok(2));
+    }
 }
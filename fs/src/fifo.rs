@@ -0,0 +1,158 @@
+use crate::*;
+use std::collections::VecDeque;
+use std::convert::TryFrom;
+use std::sync::{Arc, Condvar, Mutex};
+use wasi::*;
+
+// Same as Linux.
+const FIFO_CAPACITY: usize = 65536;
+// Writes of at most this many bytes are never interleaved with other writes.
+const PIPE_BUF: usize = 4096;
+
+/// A named pipe. Clones share the same buffer.
+#[derive(Clone, Default)]
+pub struct Fifo {
+    inner: Arc<FifoInner>,
+}
+
+#[derive(Default)]
+struct FifoInner {
+    state: Mutex<FifoState>,
+    /// Notified whenever the buffer changes or an end is closed.
+    cond: Condvar,
+}
+
+#[derive(Default)]
+struct FifoState {
+    buf: VecDeque<u8>,
+    readers: usize,
+    writers: usize,
+    // Programs sharing a FIFO are usually started together, so a reader that shows up
+    // before any writer waits for one instead of seeing EOF (and vice versa for EPIPE).
+    had_readers: bool,
+    had_writers: bool,
+}
+
+impl Fifo {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn open(&self, read: bool, write: bool) -> FifoEnd {
+        let mut state = self.inner.state.lock().unwrap();
+        if read {
+            state.readers += 1;
+            state.had_readers = true;
+        }
+        if write {
+            state.writers += 1;
+            state.had_writers = true;
+        }
+        self.inner.cond.notify_all();
+        FifoEnd {
+            fifo: self.clone(),
+            read,
+            write,
+        }
+    }
+
+    /// Reads up to `len` bytes into the host's buffer. Blocks until there is something to
+    /// read unless `nonblock` is set. Returns 0 once all writers are gone.
+    pub fn read(&self, len: usize, nonblock: bool) -> Result<u64> {
+        let mut state = self.inner.state.lock().unwrap();
+        while state.buf.is_empty() {
+            if state.had_writers && state.writers == 0 {
+                return Ok(0);
+            }
+            if nonblock {
+                return Err(ERRNO_AGAIN);
+            }
+            state = self.inner.cond.wait(state).unwrap();
+        }
+        let n = len.min(state.buf.len());
+        let buf = state.buf.drain(..n).collect::<Vec<_>>();
+        drop(state);
+        self.inner.cond.notify_all();
+        read_buf(&buf, 0)
+    }
+
+    /// Writes `len` bytes from the host's buffer. Blocks until everything fits unless
+    /// `nonblock` is set, in which case only what fits is written.
+    pub fn write(&self, len: u64, nonblock: bool) -> Result<u64> {
+        let len = usize::try_from(len).map_err(|_| ERRNO_NOMEM)?;
+        let mut buf = vec![0; len];
+        unsafe { write(buf.as_mut_ptr()) };
+
+        let mut nwritten = 0;
+        let mut state = self.inner.state.lock().unwrap();
+        while nwritten < len {
+            if state.had_readers && state.readers == 0 {
+                return if nwritten > 0 {
+                    Ok(nwritten as u64)
+                } else {
+                    Err(ERRNO_PIPE)
+                };
+            }
+            let room = FIFO_CAPACITY - state.buf.len();
+            if room == 0 || (len <= PIPE_BUF && room < len) {
+                if nonblock {
+                    return if nwritten > 0 {
+                        Ok(nwritten as u64)
+                    } else {
+                        Err(ERRNO_AGAIN)
+                    };
+                }
+                state = self.inner.cond.wait(state).unwrap();
+                continue;
+            }
+            let n = room.min(len - nwritten);
+            state.buf.extend(&buf[nwritten..nwritten + n]);
+            nwritten += n;
+            self.inner.cond.notify_all();
+        }
+        return Ok(nwritten as u64);
+
+        extern "C" {
+            fn write(ptr: *mut u8);
+        }
+    }
+}
+
+/// One descriptor's hold on a FIFO. It counts as a reader and/or writer until dropped.
+pub struct FifoEnd {
+    fifo: Fifo,
+    read: bool,
+    write: bool,
+}
+
+impl FifoEnd {
+    pub fn reader(&self) -> Result<&Fifo> {
+        if self.read {
+            Ok(&self.fifo)
+        } else {
+            Err(ERRNO_BADF)
+        }
+    }
+
+    pub fn writer(&self) -> Result<&Fifo> {
+        if self.write {
+            Ok(&self.fifo)
+        } else {
+            Err(ERRNO_BADF)
+        }
+    }
+}
+
+impl Drop for FifoEnd {
+    fn drop(&mut self) {
+        let inner = &self.fifo.inner;
+        let mut state = inner.state.lock().unwrap();
+        if self.read {
+            state.readers -= 1;
+        }
+        if self.write {
+            state.writers -= 1;
+        }
+        inner.cond.notify_all();
+    }
+}
@@ -2,113 +2,347 @@ use crate::*;
 use parking_lot::RwLock;
 use std::convert::TryFrom;
 use std::mem::MaybeUninit;
+use std::ops::Deref;
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
 use std::sync::{Arc, Mutex};
 use wasi::*;
 
-pub enum File {
+// Linux gives up after 40 links, so do we.
+const SYMLINK_MAX_HOPS: usize = 40;
+
+// Flags for `Dir::rename`, same values as Linux's `renameat2`.
+pub const RENAME_NOREPLACE: u32 = 1;
+pub const RENAME_EXCHANGE: u32 = 2;
+
+// Bits for `File::check_access`, same values as `access(2)`.
+pub const R_OK: u32 = 4;
+pub const W_OK: u32 = 2;
+pub const X_OK: u32 = 1;
+
+pub struct File {
+    pub kind: FileKind,
+    /// Unique for the lifetime of the file, which includes renames.
+    pub ino: u64,
+    /// The number of `DirEntry`s pointing at this file. Dirs can't be hard linked, so
+    /// they always report 1 (like btrfs does) rather than counting their subdirs.
+    pub nlink: u64,
+    /// Permission bits (`0o7777`), without the file type.
+    mode: AtomicU32,
+    // Timestamps are in nanoseconds. They're atomic so that they can be bumped while only
+    // holding a read lock, e.g. when reading from a file.
+    atim: AtomicU64,
+    mtim: AtomicU64,
+    ctim: AtomicU64,
+    /// Shared so they can be waited on without holding the file's lock.
+    pub locks: Arc<Locks>,
+}
+
+pub enum FileKind {
     Dir(Dir),
     RegularFile(RegularFile),
+    Symlink(Symlink),
+    CharDevice(CharDevice),
+    Fifo(Fifo),
 }
 
 impl File {
-    pub fn filetype(&self) -> Filetype {
-        if self.is_dir() {
-            FILETYPE_DIRECTORY
+    pub fn new(kind: FileKind) -> Self {
+        let now = now();
+        let mode = match &kind {
+            FileKind::Dir(_) => 0o777 & !umask(),
+            FileKind::RegularFile(_) | FileKind::Fifo(_) => 0o666 & !umask(),
+            // Links are always `rwxrwxrwx`, their mode is never checked.
+            FileKind::Symlink(_) => 0o777,
+            FileKind::CharDevice(_) => 0o666,
+        };
+        Self {
+            kind,
+            ino: next_ino(),
+            nlink: 0,
+            mode: AtomicU32::new(mode),
+            atim: AtomicU64::new(now),
+            mtim: AtomicU64::new(now),
+            ctim: AtomicU64::new(now),
+            locks: Default::default(),
+        }
+    }
+
+    pub fn atim(&self) -> u64 {
+        self.atim.load(Ordering::Relaxed)
+    }
+
+    pub fn mtim(&self) -> u64 {
+        self.mtim.load(Ordering::Relaxed)
+    }
+
+    pub fn ctim(&self) -> u64 {
+        self.ctim.load(Ordering::Relaxed)
+    }
+
+    pub fn mode(&self) -> u32 {
+        self.mode.load(Ordering::Relaxed)
+    }
+
+    pub fn set_mode(&self, mode: u32) {
+        self.mode.store(mode & 0o7777, Ordering::Relaxed);
+        self.changed();
+    }
+
+    /// Checks `access` (a mask of `R_OK`, `W_OK` and `X_OK`) against the owner bits.
+    /// There is a single user and it owns every file.
+    pub fn check_access(&self, access: u32) -> Result<()> {
+        let owner = (self.mode() >> 6) & 0o7;
+        if (owner & access) == access {
+            Ok(())
         } else {
-            FILETYPE_REGULAR_FILE
+            Err(ERRNO_ACCES)
+        }
+    }
+
+    /// Call after the contents of the file have been read.
+    pub fn accessed(&self) {
+        self.atim.store(now(), Ordering::Relaxed);
+    }
+
+    /// Call after the contents of the file have changed.
+    pub fn modified(&self) {
+        let now = now();
+        self.mtim.store(now, Ordering::Relaxed);
+        self.ctim.store(now, Ordering::Relaxed);
+    }
+
+    /// Call after the metadata of the file has changed.
+    pub fn changed(&self) {
+        self.ctim.store(now(), Ordering::Relaxed);
+    }
+
+    pub fn set_times(&self, atim: u64, mtim: u64, fst_flags: Fstflags) -> Result<()> {
+        if (fst_flags & FSTFLAGS_ATIM) != 0 && (fst_flags & FSTFLAGS_ATIM_NOW) != 0
+            || (fst_flags & FSTFLAGS_MTIM) != 0 && (fst_flags & FSTFLAGS_MTIM_NOW) != 0
+        {
+            return Err(ERRNO_INVAL);
+        }
+        let now = now();
+        if (fst_flags & FSTFLAGS_ATIM) != 0 {
+            self.atim.store(atim, Ordering::Relaxed);
+        } else if (fst_flags & FSTFLAGS_ATIM_NOW) != 0 {
+            self.atim.store(now, Ordering::Relaxed);
+        }
+        if (fst_flags & FSTFLAGS_MTIM) != 0 {
+            self.mtim.store(mtim, Ordering::Relaxed);
+        } else if (fst_flags & FSTFLAGS_MTIM_NOW) != 0 {
+            self.mtim.store(now, Ordering::Relaxed);
+        }
+        self.ctim.store(now, Ordering::Relaxed);
+        Ok(())
+    }
+
+    pub fn filetype(&self) -> Filetype {
+        match &self.kind {
+            FileKind::Dir(_) => FILETYPE_DIRECTORY,
+            FileKind::RegularFile(_) => FILETYPE_REGULAR_FILE,
+            FileKind::Symlink(_) => FILETYPE_SYMBOLIC_LINK,
+            FileKind::CharDevice(_) => FILETYPE_CHARACTER_DEVICE,
+            // WASI has no filetype for FIFOs.
+            FileKind::Fifo(_) => FILETYPE_UNKNOWN,
         }
     }
 
     pub fn size(&self) -> u64 {
-        if let Ok(file) = self.as_regular_file() {
-            file.size()
-        } else {
-            0
+        match &self.kind {
+            FileKind::RegularFile(file) => file.size(),
+            // Like POSIX, the size of a link is the length of its target.
+            FileKind::Symlink(link) => link.target.len() as u64,
+            FileKind::Dir(_) | FileKind::CharDevice(_) | FileKind::Fifo(_) => 0,
+        }
+    }
+
+    /// Reads into the host's buffer, which is `len` bytes long.
+    pub fn read(&self, len: usize, pos: u64) -> Result<u64> {
+        match &self.kind {
+            FileKind::RegularFile(file) => file.read(len, pos),
+            FileKind::CharDevice(dev) => dev.read(len),
+            _ => Err(ERRNO_BADF),
+        }
+    }
+
+    /// Writes `len` bytes from the host's buffer.
+    pub fn write(&mut self, len: u64, pos: u64) -> Result<u64> {
+        match &mut self.kind {
+            FileKind::RegularFile(file) => file.write(len, pos),
+            FileKind::CharDevice(dev) => dev.write(len),
+            _ => Err(ERRNO_BADF),
         }
     }
 
     #[allow(dead_code)]
     pub fn is_regular_file(&self) -> bool {
-        matches!(self, Self::RegularFile(_))
+        matches!(self.kind, FileKind::RegularFile(_))
     }
 
     pub fn is_dir(&self) -> bool {
-        matches!(self, Self::Dir(_))
+        matches!(self.kind, FileKind::Dir(_))
     }
 
     pub fn as_regular_file(&self) -> Result<&RegularFile> {
-        match self {
-            Self::RegularFile(file) => Ok(file),
+        match &self.kind {
+            FileKind::RegularFile(file) => Ok(file),
             _ => Err(ERRNO_BADF),
         }
     }
 
     pub fn as_regular_file_mut(&mut self) -> Result<&mut RegularFile> {
-        match self {
-            Self::RegularFile(file) => Ok(file),
+        match &mut self.kind {
+            FileKind::RegularFile(file) => Ok(file),
             _ => Err(ERRNO_BADF),
         }
     }
 
     pub fn as_dir(&self) -> Result<&Dir> {
-        match self {
-            Self::Dir(file) => Ok(file),
+        match &self.kind {
+            FileKind::Dir(file) => Ok(file),
             _ => Err(ERRNO_BADF),
         }
     }
-}
 
-pub enum RegularFile {
-    URL {
-        url: String,
-        len: Mutex<Option<u64>>,
-    },
-    Buf(Vec<u8>),
-}
+    pub fn as_symlink(&self) -> Result<&Symlink> {
+        match &self.kind {
+            FileKind::Symlink(link) => Ok(link),
+            _ => Err(ERRNO_INVAL),
+        }
+    }
 
-impl Drop for RegularFile {
-    fn drop(&mut self) {
-        self.free_url();
+    pub fn as_char_device(&self) -> Option<&CharDevice> {
+        match &self.kind {
+            FileKind::CharDevice(dev) => Some(dev),
+            _ => None,
+        }
     }
-}
 
-impl RegularFile {
-    fn as_buf(&self) -> Option<&Vec<u8>> {
-        match self {
-            Self::Buf(buf) => Some(buf),
+    pub fn as_fifo(&self) -> Option<&Fifo> {
+        match &self.kind {
+            FileKind::Fifo(fifo) => Some(fifo),
             _ => None,
         }
     }
+}
 
-    fn as_buf_mut(&mut self) -> Option<&mut Vec<u8>> {
+/// The character devices in `/dev`.
+#[derive(Clone)]
+pub enum CharDevice {
+    Null,
+    Zero,
+    Urandom,
+    Stdin,
+    Stdout,
+    Stderr,
+}
+
+impl CharDevice {
+    /// The descriptor that a stdio device stands for, see `FDTable::get_io`.
+    pub fn fd(&self) -> Option<Fd> {
         match self {
-            Self::Buf(buf) => Some(buf),
+            Self::Stdin => Some(0),
+            Self::Stdout => Some(1),
+            Self::Stderr => Some(2),
             _ => None,
         }
     }
 
-    fn free_url(&self) {
-        match self {
-            Self::URL { url, .. } => {
-                unsafe { url_free(url.as_ptr(), url.len()) };
+    pub fn read(&self, len: usize) -> Result<u64> {
+        return match self {
+            Self::Zero => read_buf(&vec![0; len], 0),
+            Self::Urandom => {
+                let mut buf = vec![0; len];
+                unsafe { random(buf.as_mut_ptr(), buf.len()) };
+                read_buf(&buf, 0)
             }
-            _ => {}
+            // Stdio devices only get here if their descriptor is closed or is the device.
+            Self::Null | Self::Stdin | Self::Stdout | Self::Stderr => Ok(0),
+        };
+
+        extern "C" {
+            fn random(ptr: *mut u8, len: usize);
+        }
+    }
+
+    /// Writes to devices are discarded.
+    pub fn write(&self, len: u64) -> Result<u64> {
+        Ok(len)
+    }
+}
+
+pub struct Symlink {
+    target: String,
+}
+
+impl Symlink {
+    pub fn new(target: &str) -> Self {
+        Self {
+            target: target.into(),
         }
+    }
+
+    pub fn target(&self) -> &str {
+        &self.target
+    }
+}
+
+pub enum RegularFile {
+    URL {
+        url: Arc<Url>,
+        len: Mutex<Option<u64>>,
+    },
+    Buf(Vec<u8>),
+    /// Files switch to this once they have a hole, e.g. after growing with `truncate`.
+    Sparse(SparseBuf),
+}
+
+/// Where the contents of a `RegularFile::URL` come from. Copies of the file share it, and
+/// the host is told to let go of it once the last of them is gone.
+pub struct Url(String);
+
+impl Deref for Url {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl Drop for Url {
+    fn drop(&mut self) {
+        unsafe { url_free(self.as_ptr(), self.len()) };
 
         extern "C" {
             fn url_free(url_ptr: *const u8, url_len: usize);
         }
     }
+}
 
-    pub fn read(&self, pos: u64) -> Result<u64> {
-        let mut nread = MaybeUninit::uninit();
-        let ok = match self {
-            Self::URL { url, .. } => unsafe {
-                url_read(url.as_ptr(), url.len(), pos, nread.as_mut_ptr())
+impl RegularFile {
+    /// A file with the same contents. URLs are shared rather than downloaded.
+    pub fn copy(&self) -> Self {
+        match self {
+            Self::URL { url, len } => Self::URL {
+                url: url.clone(),
+                len: Mutex::new(*len.lock().unwrap()),
             },
-            Self::Buf(buf) => unsafe { read(buf.as_ptr(), buf.len(), pos, nread.as_mut_ptr()) },
+            Self::Buf(buf) => Self::Buf(buf.clone()),
+            Self::Sparse(buf) => Self::Sparse(buf.clone()),
+        }
+    }
+
+    /// Reads into the host's buffer, which is `len` bytes long.
+    pub fn read(&self, len: usize, pos: u64) -> Result<u64> {
+        let url = match self {
+            Self::URL { url, .. } => url,
+            Self::Buf(buf) => return read_buf(buf, pos),
+            Self::Sparse(buf) => return read_buf(&buf.read(pos, len), 0),
         };
 
+        let mut nread = MaybeUninit::uninit();
+        let ok = unsafe { url_read(url.as_ptr(), url.len(), pos, nread.as_mut_ptr()) };
         if ok == 0 {
             let nread = unsafe { nread.assume_init() };
             return Ok(nread);
@@ -118,24 +352,35 @@ impl RegularFile {
 
         extern "C" {
             fn url_read(url_ptr: *const u8, url_len: usize, pos: u64, nread_ptr: *mut u64) -> u32;
-
-            fn read(ptr: *const u8, len: usize, pos: u64, nread_ptr: *mut u64) -> u32;
         }
     }
 
     pub fn write(&mut self, len: u64, pos: u64) -> Result<u64> {
-        self.to_buf()?;
+        self.make_buf()?;
+        // Writing past the end leaves a hole.
+        if pos > self.size() {
+            self.make_sparse();
+        }
 
-        if let Some((len, pos)) = usize::try_from(len).ok().zip(usize::try_from(pos).ok()) {
-            if len > self.as_buf().unwrap().len() - pos {
-                self.allocate(len as u64, pos as u64)?;
+        let len = usize::try_from(len).map_err(|_| ERRNO_NOMEM)?;
+        match self {
+            Self::Buf(buf) => {
+                // `pos` is within the buffer, but the end might not fit.
+                let pos = pos as usize;
+                let end = pos.checked_add(len).ok_or(ERRNO_NOMEM)?;
+                if end > buf.len() {
+                    buf.resize(end, 0);
+                }
+                unsafe { write(buf.as_mut_ptr().add(pos)) };
             }
-            let buf = self.as_buf_mut().unwrap();
-            unsafe { write(buf.as_mut_ptr().add(pos)) };
-            return Ok(len as u64);
-        } else {
-            return Err(ERRNO_NOMEM);
+            Self::Sparse(buf) => {
+                let mut data = vec![0; len];
+                unsafe { write(data.as_mut_ptr()) };
+                buf.write(pos, &data).ok_or(ERRNO_NOMEM)?;
+            }
+            Self::URL { .. } => unreachable!(),
         }
+        return Ok(len as u64);
 
         extern "C" {
             fn write(ptr: *mut u8);
@@ -158,6 +403,9 @@ impl RegularFile {
             Self::Buf(buf) => {
                 return buf.len() as u64;
             }
+            Self::Sparse(buf) => {
+                return buf.size();
+            }
         }
 
         extern "C" {
@@ -166,37 +414,56 @@ impl RegularFile {
     }
 
     pub fn truncate(&mut self, size: u64) -> Result<()> {
-        self.to_buf()?;
+        // Nothing's kept, so there's no need to fetch URLs first.
+        if size == 0 {
+            *self = Self::Buf(Vec::new());
+            return Ok(());
+        }
+        self.make_buf()?;
 
-        if let Ok(size) = usize::try_from(size) {
-            let buf_len = self.as_buf().unwrap().len();
-            if size == 0 {
-                *self.as_buf_mut().unwrap() = Vec::new();
-            } else if size > buf_len {
-                self.allocate(0, size as u64)?;
-            } else if size != buf_len {
-                self.as_buf_mut().unwrap().truncate(size);
-            }
-            Ok(())
-        } else {
-            Err(ERRNO_NOMEM)
+        if size > self.size() {
+            self.make_sparse();
         }
+        match self {
+            Self::Buf(buf) => buf.truncate(size as usize),
+            Self::Sparse(buf) => buf.set_size(size),
+            Self::URL { .. } => unreachable!(),
+        }
+        Ok(())
     }
 
+    /// Grows the file to at least `offset + len`. The new space is a hole.
     pub fn allocate(&mut self, offset: u64, len: u64) -> Result<()> {
-        self.to_buf()?;
+        let size = offset.checked_add(len).ok_or(ERRNO_FBIG)?;
+        if size > self.size() {
+            self.truncate(size)?;
+        }
+        Ok(())
+    }
 
-        if let Ok(cap) = usize::try_from(offset + len) {
-            let buf = self.as_buf_mut().unwrap();
-            buf.reserve(cap);
-            buf.resize(cap, 0);
-            Ok(())
-        } else {
-            Err(ERRNO_NOMEM)
+    /// For `SEEK_DATA`. Only a sparse file can have holes before its end.
+    pub fn next_data(&self, offset: u64) -> Option<u64> {
+        match self {
+            Self::Sparse(buf) => buf.next_data(offset),
+            _ => (offset < self.size()).then_some(offset),
         }
     }
 
-    fn to_buf(&mut self) -> Result<()> {
+    /// For `SEEK_HOLE`.
+    pub fn next_hole(&self, offset: u64) -> Option<u64> {
+        match self {
+            Self::Sparse(buf) => buf.next_hole(offset),
+            _ => (offset < self.size()).then_some(self.size()),
+        }
+    }
+
+    fn make_sparse(&mut self) {
+        if let Self::Buf(buf) = self {
+            *self = Self::Sparse(SparseBuf::new(std::mem::take(buf)));
+        }
+    }
+
+    fn make_buf(&mut self) -> Result<()> {
         let buf = match self {
             Self::URL { url, .. } => {
                 let mut len = MaybeUninit::uninit();
@@ -206,10 +473,9 @@ impl RegularFile {
                 }
                 // If the allocation succeeds then this cast is valid.
                 let len = unsafe { len.assume_init() as usize };
-                self.free_url();
                 Some(read_bytes(ptr, len))
             }
-            Self::Buf(_) => None,
+            Self::Buf(_) | Self::Sparse(_) => None,
         };
         if let Some(buf) = buf {
             *self = Self::Buf(buf);
@@ -225,23 +491,50 @@ impl RegularFile {
 pub struct Dir {
     entries: DirEntriesKey,
     is_preopen: bool,
+    /// Paths can't resolve outside of this dir (relative to the root dir), not even
+    /// through symlinks. Only set on the views returned by `confine`.
+    root: String,
+    /// What `..` does in `root`.
+    escape: RootEscape,
 }
 
 impl Dir {
-    pub fn new<'a>(entries: DirEntriesKey, is_preopen: bool) -> Self {
+    pub fn new(entries: DirEntriesKey, is_preopen: bool) -> Self {
         Self {
             entries,
             is_preopen,
+            root: String::new(),
+            escape: RootEscape::Clamp,
         }
     }
 
-    pub fn mount<'a>(
-        &self,
-        is_node: bool,
-        src: &str,
-        path: &str,
-        ents: &mut DirEntries,
-    ) -> Result<()> {
+    /// The key of this dir's entries in `DIR_ENTRIES`.
+    pub fn key(&self) -> DirEntriesKey {
+        self.entries
+    }
+
+    /// Adds `ent` after the entries already in this dir. A dir moved here learns where it
+    /// is now, see `dir_path`.
+    pub fn push(&self, ent: DirEntry, ents: &mut DirEntries) {
+        if let Some(list) = ent.entries.and_then(|key| ents.get_mut(&key)) {
+            list.set_parent(self.entries, ent.name.clone());
+        }
+        ents.get_mut(&self.entries).unwrap().push(ent);
+    }
+
+    /// A view of this dir where paths are confined to `root` (when there is one), like a
+    /// chroot. This is how each preopen only gets to see its own subtree. Whether `..` can
+    /// be used at `root` is up to the preopen, see `RootEscape`.
+    pub fn confine(&self, root: Option<&ConfinedRoot>) -> Self {
+        Self {
+            entries: self.entries,
+            is_preopen: self.is_preopen,
+            root: root.map(|root| root.path.clone()).unwrap_or_default(),
+            escape: root.map_or(self.escape, |root| root.escape),
+        }
+    }
+
+    pub fn mount(&self, is_node: bool, src: &str, path: &str, ents: &mut DirEntries) -> Result<()> {
         if src.starts_with("http:")
             || src.starts_with("https:")
             || src.starts_with("file:")
@@ -250,8 +543,10 @@ impl Dir {
         {
             self.write_file(path, 0, Some(src), ents)?;
         } else {
-            if path != "." {
-                self.mkdir(path, ents)?;
+            // Mounting onto an existing dir (like the cwd) adds to it.
+            match self.mkdir(path, false, ents) {
+                Err(ERRNO_EXIST) if self.lookup(path, true, ents)?.is_dir() => {}
+                res => res?,
             }
 
             if src.is_empty() {
@@ -287,59 +582,175 @@ impl Dir {
         }
     }
 
-    pub fn lookup<'a>(&self, path: &str, ents: &'a DirEntries) -> Result<&'a DirEntry> {
+    pub fn lookup<'a>(
+        &self,
+        path: &str,
+        follow: bool,
+        ents: &'a DirEntries,
+    ) -> Result<&'a DirEntry> {
         let path = self.resolve_path(path)?;
-        if path.is_empty() {
-            // TODO: this assumes `self` is the root dir.
-            Ok(&ROOT_DIR_ENTRY)
+        // Like POSIX, a trailing `/` follows symlinks (even for `lstat`).
+        let ResolvedEntry { entry, .. } = self.resolve_entry(&path, follow || path.dir, ents)?;
+        if let Some(entry) = entry {
+            Ok(entry)
         } else {
-            let ResolvedEntry { entry, .. } = self.resolve_entry(&path, ents);
-            if let Some(entry) = entry {
-                Ok(entry)
-            } else {
-                Err(ERRNO_NOENT)
-            }
+            Err(ERRNO_NOENT)
         }
     }
 
-    pub fn mkdir(&self, path: &str, ents: &mut DirEntries) -> Result<()> {
+    /// Creates the dir at `path`. With `recursive`, missing parents are created too and
+    /// it's fine for the dir to exist already, like `mkdir -p`.
+    pub fn mkdir(&self, path: &str, recursive: bool, ents: &mut DirEntries) -> Result<()> {
         let path = self.resolve_path(path)?;
+        if !recursive {
+            return self.create_dir(&path, ents);
+        }
+
+        let comps = path.path.split("/").collect::<Vec<_>>();
+        for end in 1..=comps.len() {
+            let path = NormalPath {
+                path: comps[..end].join("/"),
+                dir: false,
+            };
+            // The dirs above the root of a confined view are there, but out of reach.
+            if path.path != self.root && is_within(&self.root, &path.path) {
+                continue;
+            }
+            // Symlinks to dirs are as good as dirs.
+            let ResolvedEntry { entry, .. } = self.resolve_entry(&path, true, ents)?;
+            match entry {
+                Some(ent) if ent.is_dir() => {}
+                Some(_) if end == comps.len() => return Err(ERRNO_EXIST),
+                Some(_) => return Err(ERRNO_NOTDIR),
+                None => self.create_dir(&path, ents)?,
+            }
+        }
+        Ok(())
+    }
+
+    /// Creates the dir at `path` (as returned by `resolve_path`), whose parent has to exist.
+    fn create_dir(&self, path: &NormalPath, ents: &mut DirEntries) -> Result<()> {
         let ResolvedEntry {
             parent,
+            parent_dir,
             entry,
             name,
-        } = self.resolve_entry(&path, ents);
+            ..
+        } = self.resolve_entry(path, false, ents)?;
 
-        if entry.is_some() || path.is_empty() {
+        if entry.is_some() || path.path.is_empty() {
             return Err(ERRNO_EXIST);
         }
         if parent.is_none() {
             return Err(ERRNO_NOENT);
         }
+        let parent_dir = parent_dir.unwrap();
+        parent_dir.read().check_access(W_OK)?;
 
         let entries = next_dir_entries_key();
-        let file = Arc::new(RwLock::new(File::Dir(Dir {
-            entries,
-            is_preopen: false,
-        })));
-        ents.get_mut(&parent.unwrap()).unwrap().push(DirEntry {
-            name: name.unwrap().into(),
-            file,
-            filetype: FILETYPE_DIRECTORY,
-            entries: Some(entries),
-            cookie: next_dir_entry_cookie(),
-        });
-        ents.insert(entries, Vec::new());
+        let file = Arc::new(RwLock::new(File::new(FileKind::Dir(Dir::new(
+            entries, false,
+        )))));
+        ents.insert(entries, DirEntryList::new());
+        let parent_dir = parent_dir.read();
+        parent_dir
+            .as_dir()
+            .unwrap()
+            .push(DirEntry::new(name.unwrap(), file), ents);
+        parent_dir.modified();
+
+        Ok(())
+    }
+
+    pub fn symlink(&self, target: &str, path: &str, ents: &mut DirEntries) -> Result<()> {
+        let path = self.resolve_path(path)?;
+        let file = File::new(FileKind::Symlink(Symlink::new(target)));
+        self.insert(&path, Arc::new(RwLock::new(file)), ents)
+    }
+
+    pub fn mkfifo(&self, path: &str, ents: &mut DirEntries) -> Result<()> {
+        let path = self.resolve_path(path)?;
+        let file = File::new(FileKind::Fifo(Fifo::new()));
+        self.insert(&path, Arc::new(RwLock::new(file)), ents)
+    }
+
+    /// `new_path` is looked up in `new_dir`, which can be confined differently.
+    pub fn link(
+        &self,
+        old_path: &str,
+        new_dir: &Dir,
+        new_path: &str,
+        follow: bool,
+        ents: &mut DirEntries,
+    ) -> Result<()> {
+        let old_path = self.resolve_path(old_path)?;
+        let new_path = new_dir.resolve_path(new_path)?;
+
+        let file = {
+            let ResolvedEntry { entry, .. } = self.resolve_entry(&old_path, follow, ents)?;
+            if entry.is_none() {
+                return Err(ERRNO_NOENT);
+            }
+            if entry.unwrap().is_dir() {
+                return Err(ERRNO_PERM);
+            }
+            entry.unwrap().file.clone()
+        };
+        new_dir.insert(&new_path, file, ents)
+    }
+
+    /// Adds a new entry for `file` at `path` (as returned by `resolve_path`).
+    fn insert(
+        &self,
+        path: &NormalPath,
+        file: Arc<RwLock<File>>,
+        ents: &mut DirEntries,
+    ) -> Result<()> {
+        let ResolvedEntry {
+            parent,
+            parent_dir,
+            entry,
+            name,
+            ..
+        } = self.resolve_entry(path, false, ents)?;
+        if entry.is_some() || path.path.is_empty() {
+            return Err(ERRNO_EXIST);
+        }
+        if parent.is_none() {
+            return Err(ERRNO_NOENT);
+        }
+        // Only dirs can be named with a trailing `/`.
+        if path.dir {
+            return Err(ERRNO_NOTDIR);
+        }
+        let parent_dir = parent_dir.unwrap();
+        parent_dir.read().check_access(W_OK)?;
+
+        let parent_dir = parent_dir.read();
+        parent_dir
+            .as_dir()
+            .unwrap()
+            .push(DirEntry::new(name.unwrap(), file), ents);
+        parent_dir.modified();
 
         Ok(())
     }
 
+    pub fn readlink(&self, path: &str, ents: &DirEntries) -> Result<String> {
+        let ent = self.lookup(path, false, ents)?;
+        let file = ent.file.read();
+        Ok(file.as_symlink()?.target().into())
+    }
+
     pub fn read_file(&self, path: &str, ents: &DirEntries) -> Result<()> {
-        let ent = self.lookup(path, ents)?;
+        let ent = self.lookup(path, true, ents)?;
         let file_r = ent.file.read();
         let file = file_r.as_regular_file()?;
-        unsafe { set_buf(file.size()) };
-        file.read(0)?;
+        file_r.check_access(R_OK)?;
+        let size = usize::try_from(file.size()).map_err(|_| ERRNO_NOMEM)?;
+        unsafe { set_buf(size as u64) };
+        file.read(size, 0)?;
+        file_r.accessed();
         return Ok(());
 
         extern "C" {
@@ -358,7 +769,7 @@ impl Dir {
 
         let file = if let Some(url) = url {
             RegularFile::URL {
-                url: url.into(),
+                url: Arc::new(Url(url.into())),
                 len: Mutex::new(None),
             }
         } else {
@@ -369,22 +780,49 @@ impl Dir {
         let ResolvedEntry {
             entry,
             parent,
+            parent_dir,
             name,
-        } = self.resolve_entry(&path, ents);
+            ..
+        } = self.resolve_entry(&path, true, ents)?;
+        if path.path.is_empty() || entry.map(|ent| ent.is_dir()).unwrap_or(false) {
+            return Err(ERRNO_ISDIR);
+        }
         if let Some(entry) = entry {
-            *entry.file.write() = File::RegularFile(file);
+            let mut file_w = entry.file.write();
+            file_w.check_access(W_OK)?;
+            // Like writing to them, what's written to a device is discarded, except for the
+            // stdio ones which only stand for the descriptors of whoever opens them.
+            if let Some(dev) = file_w.as_char_device() {
+                return if dev.fd().is_some() {
+                    Err(ERRNO_INVAL)
+                } else {
+                    Ok(())
+                };
+            }
+            // Turning it into a regular file would go unnoticed by the dir entries that
+            // have its type, and what's written to a pipe can't be replaced anyway.
+            if file_w.as_fifo().is_some() {
+                return Err(ERRNO_INVAL);
+            }
+            file_w.kind = FileKind::RegularFile(file);
+            file_w.modified();
         } else {
             if parent.is_none() {
                 return Err(ERRNO_NOENT);
             }
-            drop(entry);
-            ents.get_mut(&parent.unwrap()).unwrap().push(DirEntry {
-                name: name.unwrap().into(),
-                filetype: FILETYPE_REGULAR_FILE,
-                entries: None,
-                file: Arc::new(RwLock::new(File::RegularFile(file))),
-                cookie: next_dir_entry_cookie(),
-            });
+            // Like `open` with `O_CREAT`.
+            if path.dir {
+                return Err(ERRNO_ISDIR);
+            }
+            let parent_dir = parent_dir.unwrap();
+            parent_dir.read().check_access(W_OK)?;
+            let file = Arc::new(RwLock::new(File::new(FileKind::RegularFile(file))));
+            let parent_dir = parent_dir.read();
+            parent_dir
+                .as_dir()
+                .unwrap()
+                .push(DirEntry::new(name.unwrap(), file), ents);
+            parent_dir.modified();
         }
 
         return Ok(());
@@ -394,51 +832,233 @@ impl Dir {
         }
     }
 
-    pub fn rename(&self, old_path: &str, new_path: &str, ents: &mut DirEntries) -> Result<()> {
+    /// Moves `old_path` to `new_path` (looked up in `new_dir`, which can be confined
+    /// differently) like POSIX `rename`, replacing whatever is there in one go. Dirs can
+    /// only replace empty dirs, and can't be moved into themselves. `flags` can be
+    /// `RENAME_NOREPLACE` or `RENAME_EXCHANGE`, like Linux's `renameat2`.
+    pub fn rename(
+        &self,
+        old_path: &str,
+        new_dir: &Dir,
+        new_path: &str,
+        flags: u32,
+        ents: &mut DirEntries,
+    ) -> Result<()> {
+        if (flags & !(RENAME_NOREPLACE | RENAME_EXCHANGE)) != 0
+            || flags == RENAME_NOREPLACE | RENAME_EXCHANGE
+        {
+            return Err(ERRNO_INVAL);
+        }
+        let exchange = (flags & RENAME_EXCHANGE) != 0;
         let from_path = self.resolve_path(old_path)?;
-        let to_path = self.resolve_path(new_path)?;
+        let to_path = new_dir.resolve_path(new_path)?;
 
-        let ent = self.resolve_entry(&from_path, ents);
-        if ent.entry.is_none() {
-            return Err(ERRNO_BADF);
-        }
-        if ent.parent.is_none() {
+        let from = self.resolve_entry(&from_path, false, ents)?;
+        let from_ent = from.entry.ok_or(ERRNO_NOENT)?;
+        if from.parent.is_none() || is_pinned(from_ent, &from.path, &self.root) {
             return Err(ERRNO_NOTCAPABLE);
         }
-
-        let to_ent = self.resolve_entry(&to_path, ents);
-        if to_ent.parent.is_none() {
-            return Err(ERRNO_NOTCAPABLE);
+        let to = new_dir.resolve_entry(&to_path, false, ents)?;
+        if to.parent.is_none() {
+            // Either the root dir or a missing parent.
+            return Err(if to.entry.is_some() {
+                ERRNO_NOTCAPABLE
+            } else {
+                ERRNO_NOENT
+            });
+        }
+        if let Some(to_ent) = to.entry {
+            // Nothing to do, like POSIX. That goes for hard links to the same file too.
+            if Arc::ptr_eq(&from_ent.file, &to_ent.file) {
+                return Ok(());
+            }
+        }
+        // A dir moved into itself would be cut off from the root dir.
+        let to_is_dir = to.entry.map(|ent| ent.is_dir()).unwrap_or(false);
+        if (from_ent.is_dir() && is_within(&to.path, &from.path))
+            || (exchange && to_is_dir && is_within(&from.path, &to.path))
+        {
+            return Err(ERRNO_INVAL);
         }
-        let to_name = to_ent.name.unwrap().to_string();
-        drop(to_ent);
 
-        let ents_key = ent.parent.unwrap();
-        let from_name = ent.name.unwrap().to_string();
-        drop(ent);
+        let replaced = match to.entry {
+            Some(to_ent) => {
+                if is_pinned(to_ent, &to.path, &new_dir.root) {
+                    return Err(ERRNO_NOTCAPABLE);
+                }
+                if (flags & RENAME_NOREPLACE) != 0 {
+                    return Err(ERRNO_EXIST);
+                }
+                if !exchange {
+                    match (from_ent.is_dir(), to_is_dir) {
+                        (true, false) => return Err(ERRNO_NOTDIR),
+                        (false, true) => return Err(ERRNO_ISDIR),
+                        (true, true) if !ents[&to_ent.entries.unwrap()].is_empty() => {
+                            return Err(ERRNO_NOTEMPTY)
+                        }
+                        _ => {}
+                    }
+                }
+                to_ent.entries.filter(|_| !exchange)
+            }
+            None if exchange => return Err(ERRNO_NOENT),
+            None if to_path.dir && !from_ent.is_dir() => return Err(ERRNO_NOTDIR),
+            None => None,
+        };
 
-        let mut ent = ents
-            .get_mut(&ents_key)
-            .unwrap()
-            .drain_filter(|x| x.name == from_name)
-            .next()
-            .unwrap();
-        ent.cookie = next_dir_entry_cookie();
+        let from_key = from.parent.unwrap();
+        let from_name = from.name.unwrap();
+        let from_dir = from.parent_dir.unwrap();
+        let to_key = to.parent.unwrap();
+        let to_name = to.name.unwrap();
+        let to_dir = to.parent_dir.unwrap();
+        from_dir.read().check_access(W_OK)?;
+        to_dir.read().check_access(W_OK)?;
+
+        let mut ent = ents.get_mut(&from_key).unwrap().remove(&from_name).unwrap();
+        if exchange {
+            let mut other = ents.get_mut(&to_key).unwrap().remove(&to_name).unwrap();
+            other.name = from_name;
+            other.file.read().changed();
+            from_dir.read().as_dir().unwrap().push(other, ents);
+        } else if let Some(entries) = replaced {
+            // It was empty, so there's nothing else to free. Whatever still has it open
+            // finds it gone, see `entries`.
+            ents.remove(&entries);
+        }
         ent.name = to_name;
+        ent.file.read().changed();
 
-        let to_ents_key = self.resolve_entry(&to_path, ents).parent.unwrap();
-        ents.get_mut(&to_ents_key).unwrap().push(ent);
+        // Like a new entry, it goes last (with a new cookie). Whatever had its name is
+        // dropped along the way.
+        to_dir.read().as_dir().unwrap().push(ent, ents);
+        from_dir.read().modified();
+        to_dir.read().modified();
 
         Ok(())
     }
 
+    /// Copies the file or dir tree at `src_path` to `dest_path`, like `cp -R`. Dirs are
+    /// merged into dirs already there, and `opts` decides what happens to other files.
+    /// Symlinks already there aren't followed, even to dirs, so a dir copied onto one fails
+    /// like it would onto a file. Unmodified URL files stay URLs, so nothing is downloaded.
+    pub fn copy(
+        &self,
+        src_path: &str,
+        dest_path: &str,
+        opts: &CopyOptions,
+        ents: &mut DirEntries,
+    ) -> Result<()> {
+        let src_path = self.resolve_path(src_path)?;
+        let dest_path = self.resolve_path(dest_path)?;
+
+        let src = self.resolve_entry(&src_path, opts.follow || src_path.dir, ents)?;
+        let src_ent = src.entry.ok_or(ERRNO_NOENT)?;
+        let dest = self.resolve_entry(&dest_path, false, ents)?;
+        if dest.parent.is_none() {
+            // Either the root dir or a missing parent.
+            return Err(if dest.entry.is_some() {
+                ERRNO_NOTCAPABLE
+            } else {
+                ERRNO_NOENT
+            });
+        }
+        if dest.entry.is_none() && dest_path.dir && !src_ent.is_dir() {
+            return Err(ERRNO_NOTDIR);
+        }
+        // Otherwise the copy would end up in what's being copied, forever.
+        if src_ent.is_dir() && is_within(&dest.path, &src.path) {
+            return Err(ERRNO_INVAL);
+        }
+
+        let source = self.copy_source(src_ent, &src.path, opts, &mut Vec::new(), ents)?;
+        let key = dest.parent.unwrap();
+        let dir = dest.parent_dir.unwrap();
+        let name = dest.name.unwrap();
+        paste(&source, key, &dir, &name, opts, ents)
+    }
+
+    /// Collects what `copy` copies from `ent`, which is at `path`. `ancestors` are the dirs
+    /// it's in, which a followed symlink mustn't lead back to.
+    fn copy_source(
+        &self,
+        ent: &DirEntry,
+        path: &str,
+        opts: &CopyOptions,
+        ancestors: &mut Vec<*const RwLock<File>>,
+        ents: &DirEntries,
+    ) -> Result<CopySource> {
+        let entries = match ent.entries {
+            Some(entries) => entries,
+            None => {
+                ent.file.read().check_access(R_OK)?;
+                return Ok(CopySource {
+                    file: ent.file.clone(),
+                    children: None,
+                });
+            }
+        };
+        if ancestors.contains(&Arc::as_ptr(&ent.file)) {
+            return Err(ERRNO_LOOP);
+        }
+        ent.file.read().check_access(R_OK | X_OK)?;
+
+        ancestors.push(Arc::as_ptr(&ent.file));
+        let mut children = Vec::new();
+        for child in ents[&entries].iter() {
+            let child_path = if path.is_empty() {
+                child.name.clone()
+            } else {
+                format!("{}/{}", path, child.name)
+            };
+            let source = if child.is_symlink() && opts.follow {
+                let path = NormalPath {
+                    path: child_path,
+                    dir: false,
+                };
+                let target = self.resolve_entry(&path, true, ents)?;
+                let target_ent = target.entry.ok_or(ERRNO_NOENT)?;
+                self.copy_source(target_ent, &target.path, opts, ancestors, ents)?
+            } else {
+                self.copy_source(child, &child_path, opts, ancestors, ents)?
+            };
+            children.push((child.name.clone(), source));
+        }
+        ancestors.pop();
+
+        Ok(CopySource {
+            file: ent.file.clone(),
+            children: Some(children),
+        })
+    }
+
+    /// Walks the dir tree at `path` for what matches `opts`, depth first and without
+    /// following symlinks, like `find`. Returns the paths found (relative to `path`) with
+    /// their files. Dirs that can't be read are skipped.
+    pub fn find(
+        &self,
+        path: &str,
+        opts: &FindOptions,
+        ents: &DirEntries,
+    ) -> Result<Vec<(String, Arc<RwLock<File>>)>> {
+        let ent = self.lookup(path, true, ents)?;
+        let entries = ent.entries.ok_or(ERRNO_NOTDIR)?;
+        ent.file.read().check_access(R_OK | X_OK)?;
+
+        let mut found = Vec::new();
+        find_in(entries, "", 1, opts, ents, &mut found);
+        Ok(found)
+    }
+
     pub fn unlink(&self, path: &str, ents: &mut DirEntries) -> Result<()> {
         let path = self.resolve_path(path)?;
         let ResolvedEntry {
             parent,
+            parent_dir,
             entry,
             name,
-        } = self.resolve_entry(&path, ents);
+            ..
+        } = self.resolve_entry(&path, false, ents)?;
 
         if entry.is_none() {
             return Err(ERRNO_NOENT);
@@ -446,9 +1066,11 @@ impl Dir {
         if entry.unwrap().is_dir() {
             return Err(ERRNO_ISDIR);
         }
-        ents.get_mut(&parent.unwrap())
-            .unwrap()
-            .drain_filter(|ent| &ent.name == name.unwrap());
+        let parent_dir = parent_dir.unwrap();
+        parent_dir.read().check_access(W_OK)?;
+        let name = name.unwrap();
+        ents.get_mut(&parent.unwrap()).unwrap().remove(&name);
+        parent_dir.read().modified();
 
         Ok(())
     }
@@ -456,12 +1078,14 @@ impl Dir {
     pub fn rmdir(&self, path: &str, recursive: bool, ents: &mut DirEntries) -> Result<()> {
         let path = self.resolve_path(path)?;
 
-        let (parent_entries, entries, name) = {
+        let (parent_entries, parent_dir, entries, name) = {
             let ResolvedEntry {
                 parent,
+                parent_dir,
                 entry,
                 name,
-            } = self.resolve_entry(&path, ents);
+                path,
+            } = self.resolve_entry(&path, false, ents)?;
             if entry.is_none() {
                 return Err(ERRNO_NOENT);
             }
@@ -473,15 +1097,22 @@ impl Dir {
             if !ents[&dir.entries].is_empty() && !recursive {
                 return Err(ERRNO_NOTEMPTY);
             }
-            if dir.is_preopen {
+            // Neither preopens nor the root of a confined view can be removed, and neither
+            // can a dir named through `..`.
+            if dir.is_preopen || parent.is_none() || path == self.root {
                 return Err(ERRNO_NOTCAPABLE);
             }
-            (parent.unwrap(), dir.entries, name.unwrap().to_string())
+            parent_dir.as_ref().unwrap().read().check_access(W_OK)?;
+            (
+                parent.unwrap(),
+                parent_dir.unwrap(),
+                dir.entries,
+                name.unwrap(),
+            )
         };
-        ents.get_mut(&parent_entries)
-            .unwrap()
-            .drain_filter(|ent| ent.name == name);
+        ents.get_mut(&parent_entries).unwrap().remove(&name);
         rmdir_recursive(entries, ents);
+        parent_dir.read().modified();
 
         return Ok(());
 
@@ -496,126 +1127,197 @@ impl Dir {
         }
     }
 
-    pub fn entries<'a>(&self, ents: &'a DirEntries) -> &'a [DirEntry] {
-        &ents[&self.entries]
+    /// Fails with ENOENT once the dir is removed, which can happen while it's still open.
+    pub fn entries<'a>(&self, ents: &'a DirEntries) -> Result<&'a DirEntryList> {
+        ents.get(&self.entries).ok_or(ERRNO_NOENT)
     }
 
+    /// `rights` are the base rights requested for the descriptor, they decide which
+    /// permission bits the file needs.
     pub fn open(
         &self,
         path: &str,
         oflags: Oflags,
+        rights: Rights,
         ents: &mut DirEntries,
     ) -> Result<Option<Arc<RwLock<File>>>> {
         let path = self.resolve_path(path)?;
 
+        let mut access = 0;
+        if (rights & (RIGHTS_FD_READ | RIGHTS_FD_READDIR)) != 0 {
+            access |= R_OK;
+        }
+        if (rights & RIGHTS_FD_WRITE) != 0 || (oflags & OFLAGS_TRUNC) != 0 {
+            access |= W_OK;
+        }
+
         if (oflags & OFLAGS_DIRECTORY) != 0 {
-            if path.is_empty() {
+            if path.path.is_empty() {
+                ROOT_DIR.read().check_access(access & R_OK)?;
                 return Ok(None);
             }
-            let ResolvedEntry { entry, .. } = self.resolve_entry(&path, ents);
+            let ResolvedEntry { entry, .. } = self.resolve_entry(&path, true, ents)?;
             if entry.is_none() {
                 return Err(ERRNO_NOENT);
             }
-            if entry.unwrap().is_regular_file() {
+            if !entry.unwrap().is_dir() {
                 return Err(ERRNO_NOTDIR);
             }
+            entry.unwrap().file.read().check_access(access & R_OK)?;
             Ok(Some(entry.unwrap().file.clone()))
         } else {
             let ResolvedEntry {
                 mut entry,
                 parent,
+                parent_dir,
                 name,
-            } = self.resolve_entry(&path, ents);
+                ..
+            } = self.resolve_entry(&path, true, ents)?;
+            if path.path.is_empty() || entry.map(|ent| ent.is_dir()).unwrap_or(false) {
+                return Err(ERRNO_ISDIR);
+            }
             if parent.is_none() {
                 return Err(ERRNO_NOENT);
             }
-            if path.is_empty() || entry.map(|ent| ent.is_dir()).unwrap_or(false) {
-                return Err(ERRNO_ISDIR);
-            }
             if entry.is_some() && (oflags & OFLAGS_CREAT) != 0 && (oflags & OFLAGS_EXCL) != 0 {
                 return Err(ERRNO_EXIST);
             }
-            if entry.is_none() {
+            if let Some(entry) = entry {
+                // Like POSIX, only files that were already there are checked. One created by
+                // this call can be written whatever its mode.
+                entry.file.read().check_access(access)?;
+            } else {
                 if (oflags & OFLAGS_CREAT) == 0 {
                     return Err(ERRNO_NOENT);
                 }
+                if path.dir {
+                    return Err(ERRNO_ISDIR);
+                }
+                let parent_dir = parent_dir.unwrap();
+                parent_dir.read().check_access(W_OK)?;
                 let key = parent.unwrap();
-                ents.get_mut(&key).unwrap().push(DirEntry {
-                    name: name.unwrap().into(),
-                    filetype: FILETYPE_REGULAR_FILE,
-                    entries: None,
-                    file: Arc::new(RwLock::new(File::RegularFile(RegularFile::Buf(Vec::new())))),
-                    cookie: next_dir_entry_cookie(),
-                });
-                entry = ents[&key].iter().find(|ent| &ent.name == name.unwrap());
-            }
-            if (oflags & OFLAGS_TRUNC) != 0 {
-                entry
-                    .unwrap()
-                    .file
-                    .write()
-                    .as_regular_file_mut()
+                let name = name.unwrap();
+                let file = Arc::new(RwLock::new(File::new(FileKind::RegularFile(
+                    RegularFile::Buf(Vec::new()),
+                ))));
+                let parent_dir = parent_dir.read();
+                parent_dir
+                    .as_dir()
                     .unwrap()
-                    .truncate(0)
-                    .unwrap();
+                    .push(DirEntry::new(name.clone(), file), ents);
+                parent_dir.modified();
+                entry = ents[&key].get(&name);
+            }
+            // Like POSIX, truncating is ignored for devices.
+            if (oflags & OFLAGS_TRUNC) != 0 && entry.unwrap().is_regular_file() {
+                let mut file = entry.unwrap().file.write();
+                file.as_regular_file_mut().unwrap().truncate(0)?;
+                file.modified();
             }
             Ok(Some(entry.unwrap().file.clone()))
         }
     }
 
-    fn resolve_path(&self, mut path: &str) -> Result<String> {
-        // Hack to get Emscripten absolute/relative paths to "work".
-        while path.starts_with("~/~/") {
-            path = &path[4..];
-        }
-        let current_dir = CURRENT_DIR.read();
-        let iter = if !path.starts_with("~") {
-            current_dir.as_str()
-        } else {
-            ""
-        }
-        .split("/")
-        .chain(path.split("/"));
-        let mut resolved_parts = Vec::new();
-        for item in iter {
-            if item == ".." {
-                if resolved_parts.pop().is_none() {
-                    return Err(ERRNO_NOTCAPABLE);
-                }
-            } else if !item.is_empty() && item != "." && item != "~" {
-                resolved_parts.push(item);
-            }
-        }
-        Ok(resolved_parts.join("/"))
+    /// Paths are relative to the root dir, see `Process::path`. This is where every path
+    /// given to a `Dir` is checked and normalized, see `path::normalize`.
+    fn resolve_path(&self, path: &str) -> Result<NormalPath> {
+        normalize(path)
     }
 
-    fn resolve_entry<'a, 'b>(&self, path: &'b str, ents: &'a DirEntries) -> ResolvedEntry<'a, 'b> {
-        let mut ret: ResolvedEntry = Default::default();
-        if !path.contains('/') {
-            ret.parent = Some(self.entries);
-        }
-        let mut entry: Option<&DirEntry> = None;
-        let comps = path.split("/").collect::<Vec<_>>();
-        for (pos, comp) in comps.iter().enumerate() {
-            if entry.is_none() {
-                entry = ents[&self.entries].iter().find(|ent| &ent.name == comp);
-            } else if entry.unwrap().is_dir() {
-                let entries = entry.unwrap().entries.unwrap();
-                entry = ents[&entries].iter().find(|ent| &ent.name == comp);
-            }
-            if Some(pos) == comps.len().checked_sub(2) {
-                if let Some(entries) = entry.and_then(|ent| ent.entries) {
-                    ret.parent = Some(entries);
+    /// Walks a path returned by `resolve_path`. Symlinks are followed along the way, and
+    /// also in the last component when `follow` is set. `..` goes up from wherever the walk
+    /// got to, so after a symlink it's the parent of the target. Absolute symlink targets
+    /// start from `root`, and anything that ends up outside of it fails with ENOTCAPABLE
+    /// (or stays in `root`, see `RootEscape`). Paths with a trailing `/` that name
+    /// something other than a dir fail with ENOTDIR.
+    fn resolve_entry<'a>(
+        &self,
+        path: &NormalPath,
+        follow: bool,
+        ents: &'a DirEntries,
+    ) -> Result<ResolvedEntry<'a>> {
+        let must_be_dir = path.dir;
+        let mut path = path.path.clone();
+        let mut hops = 0;
+        'resolve: loop {
+            let mut ret: ResolvedEntry = Default::default();
+            let comps = path
+                .split("/")
+                .filter(|comp| !comp.is_empty())
+                .collect::<Vec<_>>();
+            // The dirs walked into so far, starting from the root dir whichever dir this is,
+            // and their names.
+            let mut dirs: Vec<&DirEntry> = vec![&ROOT_DIR_ENTRY];
+            let mut names: Vec<&str> = vec![];
+            for (pos, comp) in comps.iter().enumerate() {
+                let is_last = pos + 1 == comps.len();
+                let dir = *dirs.last().unwrap();
+                dir.file.read().check_access(X_OK)?;
+                if *comp == ".." {
+                    if dot_dot(&names.join("/"), &self.root, self.escape)? {
+                        dirs.pop();
+                        names.pop();
+                    }
+                    continue;
+                }
+                // The dirs above `root` can be walked through, but not into.
+                let walked = names.iter().chain([comp]).copied().collect::<Vec<_>>();
+                let walked = walked.join("/");
+                if !is_within(&walked, &self.root) && (is_last || !is_within(&self.root, &walked)) {
+                    return Err(ERRNO_NOTCAPABLE);
+                }
+                let entries = dir.entries.unwrap();
+                let entry = ents[&entries].get(comp);
+                match entry {
+                    Some(ent) if ent.is_symlink() && (follow || !is_last) => {
+                        hops += 1;
+                        if hops > SYMLINK_MAX_HOPS {
+                            return Err(ERRNO_LOOP);
+                        }
+                        let file = ent.file.read();
+                        let target = file.as_symlink()?.target();
+                        // Relative targets are relative to the directory holding the link.
+                        let base = if target.starts_with("/") {
+                            self.root.clone()
+                        } else {
+                            names.join("/")
+                        };
+                        let rest = comps[pos + 1..].join("/");
+                        let next = join(&base, &format!("{}/{}", target, rest))?;
+                        drop(file);
+                        path = next.path;
+                        continue 'resolve;
+                    }
+                    Some(ent) if !is_last => {
+                        if ent.entries.is_none() {
+                            return Err(ERRNO_NOTDIR);
+                        }
+                        dirs.push(ent);
+                        names.push(comp);
+                    }
+                    // A missing intermediate dir leaves `parent` unset.
+                    None if !is_last => return Ok(ret),
+                    Some(ent) if must_be_dir && !ent.is_dir() => return Err(ERRNO_NOTDIR),
+                    entry => {
+                        ret.parent = Some(entries);
+                        ret.parent_dir = Some(dir.file.clone());
+                        ret.entry = entry;
+                        ret.name = Some(comp.to_string());
+                        ret.path = walked;
+                        return Ok(ret);
+                    }
                 }
-            } else if Some(pos) == comps.len().checked_sub(1) {
-                ret.entry = entry;
-                ret.name = Some(comp);
             }
-            if entry.is_none() {
-                break;
+            // Ending in `..` (or at the root dir) names a dir but not an entry in a parent
+            // that could be replaced or removed, so `parent` is left unset like for the root.
+            let path = names.join("/");
+            if !is_within(&path, &self.root) {
+                return Err(ERRNO_NOTCAPABLE);
             }
+            ret.entry = dirs.last().copied();
+            ret.path = path;
+            return Ok(ret);
         }
-        ret
     }
 }
 
@@ -625,9 +1327,32 @@ pub struct DirEntry {
     pub filetype: Filetype,
     pub entries: Option<DirEntriesKey>,
     pub cookie: u64,
+    pub ino: u64,
 }
 
 impl DirEntry {
+    pub fn new(name: String, file: Arc<RwLock<File>>) -> Self {
+        let (filetype, entries, ino) = {
+            let mut file = file.write();
+            file.nlink += 1;
+            file.changed();
+            (
+                file.filetype(),
+                file.as_dir().ok().map(|dir| dir.entries),
+                file.ino,
+            )
+        };
+        Self {
+            name,
+            file,
+            filetype,
+            entries,
+            // Set by `DirEntryList::push`.
+            cookie: 0,
+            ino,
+        }
+    }
+
     pub fn is_dir(&self) -> bool {
         matches!(self.filetype, FILETYPE_DIRECTORY)
     }
@@ -635,11 +1360,276 @@ impl DirEntry {
     pub fn is_regular_file(&self) -> bool {
         matches!(self.filetype, FILETYPE_REGULAR_FILE)
     }
+
+    pub fn is_symlink(&self) -> bool {
+        matches!(self.filetype, FILETYPE_SYMBOLIC_LINK)
+    }
+}
+
+impl Drop for DirEntry {
+    fn drop(&mut self) {
+        let mut file = self.file.write();
+        file.nlink -= 1;
+        file.changed();
+    }
+}
+
+/// Whether `ent` (at `path`) has to stay where it is: preopens can't be moved or replaced,
+/// and neither can the `root` of a confined view.
+fn is_pinned(ent: &DirEntry, path: &str, root: &str) -> bool {
+    path == root
+        || ent
+            .file
+            .read()
+            .as_dir()
+            .map(|dir| dir.is_preopen)
+            .unwrap_or(false)
+}
+
+/// What `Dir::copy` does with files that are already where it copies to. Dirs are merged
+/// whatever it is.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Overwrite {
+    /// Fails with EEXIST.
+    Never,
+    /// Leaves them be.
+    Skip,
+    /// Replaces them, unless that would put a dir in place of a file or the other way
+    /// around.
+    Replace,
+}
+
+pub struct CopyOptions {
+    pub overwrite: Overwrite,
+    /// Keeps modes and times, like `cp -p`. Otherwise copies get the mode of what they're
+    /// copied from less the umask, and the current time.
+    pub preserve: bool,
+    /// Copies what symlinks point to rather than the links themselves, like `cp -L`.
+    pub follow: bool,
+}
+
+/// A file or dir tree as `Dir::copy` found it, before anything is copied.
+struct CopySource {
+    file: Arc<RwLock<File>>,
+    /// What's in it, for dirs.
+    children: Option<Vec<(String, CopySource)>>,
+}
+
+/// Puts a copy of `source` in the dir whose entries are `entries` (`dir` itself), as
+/// `name`. See `Dir::copy`.
+fn paste(
+    source: &CopySource,
+    entries: DirEntriesKey,
+    dir: &Arc<RwLock<File>>,
+    name: &str,
+    opts: &CopyOptions,
+    ents: &mut DirEntries,
+) -> Result<()> {
+    let existing = ents[&entries]
+        .get(name)
+        .map(|ent| (ent.entries, ent.file.clone()));
+    if let Some((existing_entries, existing_file)) = existing {
+        match (&source.children, existing_entries) {
+            (Some(children), Some(existing_entries)) => {
+                for (name, child) in children {
+                    paste(child, existing_entries, &existing_file, name, opts, ents)?;
+                }
+                if opts.preserve {
+                    copy_metadata(&source.file.read(), &existing_file.read(), true)?;
+                }
+                return Ok(());
+            }
+            _ if opts.overwrite == Overwrite::Never => return Err(ERRNO_EXIST),
+            _ if opts.overwrite == Overwrite::Skip => return Ok(()),
+            (Some(_), None) => return Err(ERRNO_NOTDIR),
+            (None, Some(_)) => return Err(ERRNO_ISDIR),
+            (None, None) => {}
+        }
+    }
+    dir.read().check_access(W_OK)?;
+
+    let file = match &source.children {
+        Some(_) => {
+            let key = next_dir_entries_key();
+            ents.insert(key, DirEntryList::new());
+            FileKind::Dir(Dir::new(key, false))
+        }
+        None => match &source.file.read().kind {
+            FileKind::RegularFile(file) => FileKind::RegularFile(file.copy()),
+            FileKind::Symlink(link) => FileKind::Symlink(Symlink::new(link.target())),
+            FileKind::CharDevice(dev) => FileKind::CharDevice(dev.clone()),
+            FileKind::Fifo(_) => FileKind::Fifo(Fifo::new()),
+            FileKind::Dir(_) => unreachable!(),
+        },
+    };
+    let file = Arc::new(RwLock::new(File::new(file)));
+    // Whatever had the name is dropped here.
+    dir.read()
+        .as_dir()
+        .unwrap()
+        .push(DirEntry::new(name.into(), file.clone()), ents);
+    dir.read().modified();
+
+    if let Some(children) = &source.children {
+        let key = file.read().as_dir().unwrap().entries;
+        for (name, child) in children {
+            paste(child, key, &file, name, opts, ents)?;
+        }
+    }
+    // Only now, as a read-only dir couldn't have been filled.
+    copy_metadata(&source.file.read(), &file.read(), opts.preserve)?;
+
+    Ok(())
+}
+
+fn copy_metadata(src: &File, dest: &File, preserve: bool) -> Result<()> {
+    if preserve {
+        dest.set_times(src.atim(), src.mtim(), FSTFLAGS_ATIM | FSTFLAGS_MTIM)?;
+    }
+    if dest.as_symlink().is_err() {
+        dest.set_mode(if preserve {
+            src.mode()
+        } else {
+            src.mode() & !umask()
+        });
+    }
+    Ok(())
+}
+
+/// What `Dir::find` looks for. Paths are relative to the dir searched, whose entries are
+/// at depth 1.
+pub struct FindOptions {
+    /// Paths have to match one of these, if there are any.
+    pub include: Vec<Glob>,
+    /// Paths can't match any of these, and neither can the dirs they're in.
+    pub exclude: Vec<Glob>,
+    pub filetype: Option<Filetype>,
+    pub min_size: Option<u64>,
+    pub max_size: Option<u64>,
+    pub min_depth: usize,
+    pub max_depth: Option<usize>,
+    /// Bounds on `mtim`, both exclusive, like `find -newermt`.
+    pub modified_after: Option<u64>,
+    pub modified_before: Option<u64>,
+}
+
+impl FindOptions {
+    fn matches(&self, path: &str, depth: usize, file: &File) -> bool {
+        if depth < self.min_depth {
+            return false;
+        }
+        if let Some(filetype) = self.filetype {
+            if file.filetype() != filetype {
+                return false;
+            }
+        }
+        // Only sizes that are asked about, as URL files have to ask the host for theirs.
+        if self.min_size.is_some() || self.max_size.is_some() {
+            let size = file.size();
+            if matches!(self.min_size, Some(min) if size < min)
+                || matches!(self.max_size, Some(max) if size > max)
+            {
+                return false;
+            }
+        }
+        let mtim = file.mtim();
+        if matches!(self.modified_after, Some(after) if mtim <= after)
+            || matches!(self.modified_before, Some(before) if mtim >= before)
+        {
+            return false;
+        }
+        self.include.is_empty() || self.include.iter().any(|glob| glob.matches(path))
+    }
+}
+
+/// Adds what `Dir::find` finds in the dir with `entries`, which is at `prefix` and whose
+/// entries are at `depth`.
+fn find_in(
+    entries: DirEntriesKey,
+    prefix: &str,
+    depth: usize,
+    opts: &FindOptions,
+    ents: &DirEntries,
+    found: &mut Vec<(String, Arc<RwLock<File>>)>,
+) {
+    for ent in ents[&entries].iter() {
+        let path = if prefix.is_empty() {
+            ent.name.clone()
+        } else {
+            format!("{}/{}", prefix, ent.name)
+        };
+        if opts.exclude.iter().any(|glob| glob.matches(&path)) {
+            continue;
+        }
+        let file = ent.file.read();
+        if opts.matches(&path, depth, &file) {
+            found.push((path.clone(), ent.file.clone()));
+        }
+        let below = ent.entries.filter(|_| {
+            !matches!(opts.max_depth, Some(max) if depth >= max)
+                && file.check_access(R_OK | X_OK).is_ok()
+        });
+        drop(file);
+        if let Some(entries) = below {
+            find_in(entries, &path, depth + 1, opts, ents, found);
+        }
+    }
 }
 
 #[derive(Default)]
-struct ResolvedEntry<'a, 'b> {
+struct ResolvedEntry<'a> {
     parent: Option<DirEntriesKey>,
+    parent_dir: Option<Arc<RwLock<File>>>,
     entry: Option<&'a DirEntry>,
-    name: Option<&'b str>,
+    name: Option<String>,
+    /// Where the entry really is, after following symlinks along the way.
+    path: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn copy_onto_symlink_to_dir() {
+        let mut ents = DIR_ENTRIES.write();
+        let root_dir = ROOT_DIR.read();
+        let dir = root_dir.as_dir().unwrap();
+        dir.mkdir("~/copy/src/sub", true, &mut ents).unwrap();
+        dir.mkdir("~/copy/dir", false, &mut ents).unwrap();
+        dir.symlink("dir", "~/copy/link", &mut ents).unwrap();
+        let copy = |dest: &str, overwrite: Overwrite, ents: &mut DirEntries| {
+            let opts = CopyOptions {
+                overwrite,
+                preserve: false,
+                follow: false,
+            };
+            dir.copy("~/copy/src", dest, &opts, ents)
+        };
+
+        assert_eq!(
+            copy("~/copy/link", Overwrite::Never, &mut ents),
+            Err(ERRNO_EXIST)
+        );
+        assert_eq!(
+            copy("~/copy/link", Overwrite::Replace, &mut ents),
+            Err(ERRNO_NOTDIR)
+        );
+        assert_eq!(copy("~/copy/link", Overwrite::Skip, &mut ents), Ok(()));
+        // Nor are they followed further down.
+        copy("~/copy/dest", Overwrite::Never, &mut ents).unwrap();
+        dir.rmdir("~/copy/dest/sub", false, &mut ents).unwrap();
+        dir.symlink("../dir", "~/copy/dest/sub", &mut ents).unwrap();
+        assert_eq!(
+            copy("~/copy/dest", Overwrite::Replace, &mut ents),
+            Err(ERRNO_NOTDIR)
+        );
+
+        assert!(dir
+            .lookup("~/copy/link", false, &ents)
+            .unwrap()
+            .is_symlink());
+        let target = dir.lookup("~/copy/dir", false, &ents).unwrap();
+        assert!(ents[&target.entries.unwrap()].is_empty());
+    }
 }
@@ -1,5 +1,6 @@
 use crate::*;
 use parking_lot::RwLock;
+use std::convert::TryFrom;
 use std::sync::Arc;
 use wasi::*;
 
@@ -7,22 +8,174 @@ pub struct FileDesc {
     pub file: Arc<RwLock<File>>,
     pub pos: u64,
     pub preopen: Option<String>,
+    /// The entries of the preopen this descriptor was opened through, which paths resolved
+    /// relative to it can't leave.
+    pub root: DirEntriesKey,
+    /// What `..` does in `root`, set by the preopen like `root` is.
+    pub escape: RootEscape,
+    /// `R_OK` and/or `W_OK`, like the access mode passed to POSIX `open`. Unlike `rights`,
+    /// this never changes.
+    pub access: u32,
+    pub rights: Rights,
+    pub rights_inheriting: Rights,
+    pub flags: Fdflags,
+    /// Set when `file` is a FIFO.
+    pub fifo: Option<FifoEnd>,
+    /// Identifies the locks taken through this descriptor, see `Locks`.
+    pub lock_owner: LockOwner,
 }
 
 impl FileDesc {
-    pub fn new(file: Arc<RwLock<File>>, pos: u64, preopen: Option<String>) -> Self {
-        Self { file, pos, preopen }
+    pub fn new(
+        file: Arc<RwLock<File>>,
+        pos: u64,
+        preopen: Option<String>,
+        rights: Rights,
+        rights_inheriting: Rights,
+    ) -> Self {
+        let mut access = 0;
+        if (rights & (RIGHTS_FD_READ | RIGHTS_FD_READDIR)) != 0 {
+            access |= R_OK;
+        }
+        if (rights & RIGHTS_FD_WRITE) != 0 {
+            access |= W_OK;
+        }
+        Self {
+            file,
+            pos,
+            preopen,
+            root: ROOT_DIR_ENTRIES,
+            escape: RootEscape::Deny,
+            access,
+            rights,
+            rights_inheriting,
+            flags: 0,
+            fifo: None,
+            lock_owner: next_lock_owner(),
+        }
     }
 
-    pub fn seek(&mut self, offset: u64, whence: Whence) -> Result<u64> {
-        let size = self.file.read().as_regular_file()?.size();
+    /// Fails with EBADF if the descriptor wasn't opened for `access`, or with ENOTCAPABLE if
+    /// it lacks any of `rights`.
+    pub fn check(&self, access: u32, rights: Rights) -> Result<()> {
+        if (self.access & access) != access {
+            return Err(ERRNO_BADF);
+        }
+        if (self.rights & rights) != rights {
+            return Err(ERRNO_NOTCAPABLE);
+        }
+        Ok(())
+    }
+
+    /// Resolves `path` relative to this dir, like the `*at` functions in POSIX. Returns it
+    /// relative to the root dir, along with the dir it's confined to (see `Dir::confine`).
+    /// Both go by where the dirs are now rather than where they were opened, so this fails
+    /// with ENOENT once this dir is removed.
+    pub fn resolve(&self, path: &str, ents: &DirEntries) -> Result<(String, ConfinedRoot)> {
+        let key = match self.file.read().as_dir() {
+            Ok(dir) => dir.key(),
+            Err(_) => return Err(ERRNO_NOTDIR),
+        };
+        check(path)?;
+        // Like POSIX, an empty path doesn't name the dir itself.
+        if path.is_empty() {
+            return Err(ERRNO_NOENT);
+        }
+        if path.starts_with("/") {
+            return Err(ERRNO_NOTCAPABLE);
+        }
+        let root = ConfinedRoot {
+            path: dir_path(self.root, ents)?,
+            escape: self.escape,
+        };
+        // Keeps a trailing `/`, see `NormalPath`.
+        let path = join(&dir_path(key, ents)?, path)?.to_string();
+        Ok((path, root))
+    }
+
+    /// Rights can only ever be taken away.
+    pub fn set_rights(&mut self, rights: Rights, rights_inheriting: Rights) -> Result<()> {
+        if (rights & !self.rights) != 0 || (rights_inheriting & !self.rights_inheriting) != 0 {
+            return Err(ERRNO_NOTCAPABLE);
+        }
+        self.rights = rights;
+        self.rights_inheriting = rights_inheriting;
+        Ok(())
+    }
+
+    pub fn set_flags(&mut self, flags: Fdflags) -> Result<()> {
+        self.check(0, RIGHTS_FD_FDSTAT_SET_FLAGS)?;
+        if (flags & !FDFLAGS_ALL) != 0 {
+            return Err(ERRNO_INVAL);
+        }
+        self.flags = flags;
+        Ok(())
+    }
+
+    pub fn append(&self) -> bool {
+        (self.flags & FDFLAGS_APPEND) != 0
+    }
+
+    pub fn nonblock(&self) -> bool {
+        (self.flags & FDFLAGS_NONBLOCK) != 0
+    }
+
+    /// Fails with EINVAL if the new position would be negative (or past `i64::MAX`), and
+    /// with ESPIPE for pipes and streams.
+    pub fn seek(&mut self, offset: i64, whence: Whence) -> Result<u64> {
+        let size = {
+            let file = self.file.read();
+            match &file.kind {
+                FileKind::RegularFile(file) => file.size(),
+                // The position of a dir is only ever a readdir cookie, so it has no end.
+                FileKind::Dir(_) if whence == WHENCE_END => return Err(ERRNO_INVAL),
+                FileKind::Dir(_) => 0,
+                FileKind::CharDevice(dev) if dev.fd().is_some() => return Err(ERRNO_SPIPE),
+                // Like Linux, the other devices accept any position.
+                FileKind::CharDevice(_) => 0,
+                FileKind::Fifo(_) | FileKind::Symlink(_) => return Err(ERRNO_SPIPE),
+            }
+        };
         let base_pos = match whence {
-            WHENCE_SET => 0u64,
+            WHENCE_SET => 0,
             WHENCE_CUR => self.pos,
             WHENCE_END => size,
             _ => unreachable!(),
         };
-        self.pos = base_pos + offset;
+        let pos = i64::try_from(base_pos)
+            .ok()
+            .and_then(|base_pos| base_pos.checked_add(offset))
+            .filter(|pos| *pos >= 0)
+            .ok_or(ERRNO_INVAL)?;
+        self.pos = pos as u64;
         Ok(self.pos)
     }
+
+    /// Moves to the next data at or after `offset` for `SEEK_DATA`, or to the next hole
+    /// when `hole` is set.
+    pub fn seek_data(&mut self, offset: i64, hole: bool) -> Result<u64> {
+        let offset = u64::try_from(offset).map_err(|_| ERRNO_NXIO)?;
+        let pos = {
+            let file = self.file.read();
+            let file = match &file.kind {
+                FileKind::RegularFile(file) => file,
+                FileKind::Dir(_) => return Err(ERRNO_INVAL),
+                _ => return Err(ERRNO_SPIPE),
+            };
+            if hole {
+                file.next_hole(offset)
+            } else {
+                file.next_data(offset)
+            }
+        };
+        self.pos = pos.ok_or(ERRNO_NXIO)?;
+        Ok(self.pos)
+    }
+}
+
+impl Drop for FileDesc {
+    fn drop(&mut self) {
+        let locks = self.file.read().locks.clone();
+        locks.release(self.lock_owner);
+    }
 }
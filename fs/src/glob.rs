@@ -0,0 +1,292 @@
+use crate::*;
+use std::str::Chars;
+use wasi::*;
+
+/// A shell-like pattern for relative paths. `*` matches any number of characters and `?`
+/// exactly one, but neither matches `/`. `[abc]`, `[a-z]` and `[!a-z]` (or `[^a-z]`) match
+/// one character of a class, and a `**` component matches any number of components. `\`
+/// takes the character after it as is. Unlike shells, `*` matches names starting with `.`.
+#[derive(Debug)]
+pub struct Glob {
+    comps: Vec<Comp>,
+}
+
+#[derive(Debug)]
+enum Comp {
+    /// `**`.
+    AnyComps,
+    Name(Vec<Token>),
+}
+
+#[derive(Debug, PartialEq)]
+enum Token {
+    Char(char),
+    /// `?`.
+    AnyChar,
+    /// `*`.
+    AnyChars,
+    Class {
+        negated: bool,
+        ranges: Vec<(char, char)>,
+    },
+}
+
+impl Glob {
+    /// Fails with EINVAL for a `[` without a `]`, or a `\` at the end. Empty and `.`
+    /// components are left out, like they are from normalized paths.
+    pub fn new(pattern: &str) -> Result<Self> {
+        let comps = pattern
+            .split("/")
+            .filter(|comp| !comp.is_empty() && *comp != ".")
+            .map(|comp| match comp {
+                "**" => Ok(Comp::AnyComps),
+                comp => parse_name(comp).map(Comp::Name),
+            })
+            .collect::<Result<_>>()?;
+        Ok(Self { comps })
+    }
+
+    /// Whether `path`, relative to wherever the pattern is relative to, matches.
+    pub fn matches(&self, path: &str) -> bool {
+        let names = path
+            .split("/")
+            .filter(|name| !name.is_empty())
+            .map(|name| name.chars().collect::<Vec<_>>())
+            .collect::<Vec<_>>();
+        match_comps(&self.comps, &names)
+    }
+}
+
+impl Token {
+    fn matches(&self, c: char) -> bool {
+        match self {
+            Self::Char(x) => *x == c,
+            Self::AnyChar => true,
+            Self::AnyChars => unreachable!(),
+            Self::Class { negated, ranges } => {
+                ranges.iter().any(|(lo, hi)| *lo <= c && c <= *hi) != *negated
+            }
+        }
+    }
+}
+
+fn parse_name(name: &str) -> Result<Vec<Token>> {
+    let mut tokens = Vec::new();
+    let mut chars = name.chars();
+    while let Some(c) = chars.next() {
+        let token = match c {
+            '?' => Token::AnyChar,
+            // More stars match what one does, only slower.
+            '*' if tokens.last() == Some(&Token::AnyChars) => continue,
+            '*' => Token::AnyChars,
+            '[' => parse_class(&mut chars)?,
+            '\\' => Token::Char(chars.next().ok_or(ERRNO_INVAL)?),
+            c => Token::Char(c),
+        };
+        tokens.push(token);
+    }
+    Ok(tokens)
+}
+
+/// Parses a class, after its `[`. Like in shells, a `]` right at the start and a `-` at
+/// either end stand for themselves.
+fn parse_class(chars: &mut Chars) -> Result<Token> {
+    let mut negated = false;
+    let mut ranges = Vec::new();
+    loop {
+        let c = chars.next().ok_or(ERRNO_INVAL)?;
+        let first = ranges.is_empty();
+        let c = match c {
+            '!' | '^' if first && !negated => {
+                negated = true;
+                continue;
+            }
+            ']' if !first => break,
+            '\\' => chars.next().ok_or(ERRNO_INVAL)?,
+            c => c,
+        };
+        let mut ahead = chars.clone();
+        let end = match (ahead.next(), ahead.next()) {
+            (Some('-'), Some(end)) if end != ']' => {
+                *chars = ahead;
+                if end == '\\' {
+                    chars.next().ok_or(ERRNO_INVAL)?
+                } else {
+                    end
+                }
+            }
+            _ => c,
+        };
+        ranges.push((c, end));
+    }
+    Ok(Token::Class { negated, ranges })
+}
+
+fn match_comps(comps: &[Comp], names: &[Vec<char>]) -> bool {
+    match_stars(
+        comps,
+        names,
+        |comp| matches!(comp, Comp::AnyComps),
+        |comp, name| match comp {
+            Comp::Name(tokens) => match_name(tokens, name),
+            Comp::AnyComps => unreachable!(),
+        },
+    )
+}
+
+fn match_name(tokens: &[Token], chars: &[char]) -> bool {
+    match_stars(
+        tokens,
+        chars,
+        |token| *token == Token::AnyChars,
+        |token, c| token.matches(*c),
+    )
+}
+
+/// Whether `items` match `pats`, where a star matches any number of items and anything
+/// else exactly one. Only the last star seen is ever backtracked to, since whatever an
+/// earlier one would match instead, the last one can match too. That keeps this at
+/// O(pats * items), rather than trying every way of splitting `items` among the stars.
+fn match_stars<P, I>(
+    pats: &[P],
+    items: &[I],
+    is_star: impl Fn(&P) -> bool,
+    matches: impl Fn(&P, &I) -> bool,
+) -> bool {
+    let (mut p, mut i) = (0, 0);
+    // The last star and how many items it matches up to, for when what follows fails.
+    let mut star = None;
+    while i < items.len() {
+        if p < pats.len() && is_star(&pats[p]) {
+            star = Some((p, i));
+            p += 1;
+        } else if p < pats.len() && matches(&pats[p], &items[i]) {
+            p += 1;
+            i += 1;
+        } else if let Some((star_p, star_i)) = star {
+            star = Some((star_p, star_i + 1));
+            p = star_p + 1;
+            i = star_i + 1;
+        } else {
+            return false;
+        }
+    }
+    pats[p..].iter().all(is_star)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn glob(pattern: &str) -> Glob {
+        Glob::new(pattern).unwrap()
+    }
+
+    #[test]
+    fn literal() {
+        assert!(glob("a/b.png").matches("a/b.png"));
+        assert!(!glob("a/b.png").matches("a/b.pn"));
+        assert!(!glob("a/b.png").matches("a"));
+        assert!(!glob("a").matches("a/b.png"));
+        assert!(glob("./a//b/").matches("a/b"));
+    }
+
+    #[test]
+    fn star() {
+        assert!(glob("*.png").matches("a.png"));
+        assert!(glob("*.png").matches(".png"));
+        assert!(glob("*.png").matches(".hidden.png"));
+        assert!(!glob("*.png").matches("a/b.png"));
+        assert!(glob("a*b*c").matches("abc"));
+        assert!(glob("a*b*c").matches("axxbyyc"));
+        assert!(!glob("a*b*c").matches("axxbyy"));
+        assert!(glob("out/*/x").matches("out/a/x"));
+        assert!(!glob("out/*/x").matches("out/x"));
+        assert!(glob("a**b").matches("axb"));
+    }
+
+    #[test]
+    fn backtracking() {
+        assert!(glob("*ab").matches("aab"));
+        assert!(glob("*a*b").matches("abab"));
+        assert!(!glob("*a*b").matches("abba"));
+        assert!(glob("**/a/b").matches("a/a/b"));
+        assert!(glob("**/a/**/b").matches("a/b/a/c/b"));
+        assert!(!glob("**/a/**/b").matches("b/a"));
+        // These would take forever trying every way of splitting the name among the stars.
+        let name = "a".repeat(100);
+        assert!(!glob(&format!("{}b", "*a".repeat(20))).matches(&name));
+        assert!(glob(&format!("{}*", "*a".repeat(20))).matches(&name));
+        let path = vec!["a"; 100].join("/");
+        assert!(!glob(&format!("{}b", "**/a/".repeat(20))).matches(&path));
+    }
+
+    #[test]
+    fn question_mark() {
+        assert!(glob("?.png").matches("a.png"));
+        assert!(!glob("?.png").matches("ab.png"));
+        assert!(!glob("?.png").matches(".png"));
+        assert!(glob("?").matches("é"));
+    }
+
+    #[test]
+    fn any_comps() {
+        let png = glob("**/*.png");
+        assert!(png.matches("a.png"));
+        assert!(png.matches("a/b.png"));
+        assert!(png.matches("a/b/c/d.png"));
+        assert!(!png.matches("a/b.jpg"));
+        let out = glob("out/**");
+        assert!(out.matches("out"));
+        assert!(out.matches("out/a/b"));
+        assert!(!out.matches("outer/a"));
+        assert!(glob("a/**/b").matches("a/b"));
+        assert!(glob("a/**/b").matches("a/x/y/b"));
+        assert!(!glob("a/**/b").matches("a/x/y/c"));
+        assert!(glob("**/**/a").matches("a"));
+    }
+
+    #[test]
+    fn class() {
+        let digit = glob("[0-9].txt");
+        assert!(digit.matches("5.txt"));
+        assert!(!digit.matches("a.txt"));
+        assert!(glob("[abc]").matches("b"));
+        assert!(!glob("[abc]").matches("d"));
+        assert!(glob("[a-cx-z]").matches("y"));
+        assert!(!glob("[a-cx-z]").matches("m"));
+    }
+
+    #[test]
+    fn negated_class() {
+        assert!(glob("[!0-9]").matches("a"));
+        assert!(!glob("[!0-9]").matches("1"));
+        assert!(glob("[^a]").matches("b"));
+        assert!(!glob("[^a]").matches("a"));
+    }
+
+    #[test]
+    fn class_edges() {
+        assert!(glob("[]]").matches("]"));
+        assert!(glob("[!]]").matches("a"));
+        assert!(!glob("[!]]").matches("]"));
+        assert!(glob("[a-]").matches("-"));
+        assert!(glob("[-a]").matches("-"));
+        assert!(glob("[\\]]").matches("]"));
+    }
+
+    #[test]
+    fn escape() {
+        assert!(glob("\\*").matches("*"));
+        assert!(!glob("\\*").matches("a"));
+        assert!(glob("a\\?").matches("a?"));
+        assert!(!glob("a\\?").matches("ab"));
+    }
+
+    #[test]
+    fn invalid() {
+        assert_eq!(Glob::new("[abc").unwrap_err(), ERRNO_INVAL);
+        assert_eq!(Glob::new("[").unwrap_err(), ERRNO_INVAL);
+        assert_eq!(Glob::new("a\\").unwrap_err(), ERRNO_INVAL);
+    }
+}
@@ -1,59 +1,111 @@
-#![feature(drain_filter)]
 #![feature(once_cell)]
 
+mod dir_entry_list;
 mod fd_table;
+mod fifo;
 mod file;
 mod file_desc;
+mod glob;
+mod lock;
+mod path;
+mod process;
+mod sparse;
 
+pub use dir_entry_list::*;
 pub use fd_table::*;
+pub use fifo::*;
 pub use file::*;
 pub use file_desc::*;
+pub use glob::*;
+pub use lock::*;
 use parking_lot::RwLock;
+pub use path::*;
+pub use process::*;
+pub use sparse::*;
 use std::collections::HashMap;
 use std::convert::TryInto;
+use std::mem::MaybeUninit;
 use std::sync::Arc;
 use std::sync::LazyLock;
 use wasi::*;
 
 type DirEntriesKey = u32;
-type DirEntries = HashMap<DirEntriesKey, Vec<DirEntry>>;
+type DirEntries = HashMap<DirEntriesKey, DirEntryList>;
 type Result<T> = std::result::Result<T, Errno>;
 
-static DIR_ENTRIES: LazyLock<RwLock<HashMap<DirEntriesKey, Vec<DirEntry>>>> = LazyLock::new(|| {
+const ROOT_DIR_ENTRIES: DirEntriesKey = 0;
+
+static DIR_ENTRIES: LazyLock<RwLock<DirEntries>> = LazyLock::new(|| {
     let mut map = HashMap::new();
-    // Root dir
-    map.insert(0, Vec::new());
+    map.insert(ROOT_DIR_ENTRIES, DirEntryList::new());
+    let dev = dev_dir_entry(&mut map);
+    ROOT_DIR.read().as_dir().unwrap().push(dev, &mut map);
     RwLock::new(map)
 });
 static NEXT_DIR_ENTRIES_KEY: RwLock<DirEntriesKey> = RwLock::new(1);
-static NEXT_DIR_ENTRY_COOKIE: RwLock<u64> = RwLock::new(0);
-static NEXT_FD: RwLock<Fd> = RwLock::new(0);
-static ROOT_DIR: LazyLock<Arc<RwLock<File>>> =
-    LazyLock::new(|| Arc::new(RwLock::new(File::Dir(Dir::new(0, true)))));
+// Some libcs treat an inode of 0 as a deleted dir entry.
+static NEXT_INO: RwLock<u64> = RwLock::new(1);
+static ROOT_DIR: LazyLock<Arc<RwLock<File>>> = LazyLock::new(|| {
+    let mut file = File::new(FileKind::Dir(Dir::new(ROOT_DIR_ENTRIES, true)));
+    // Accounts for `ROOT_DIR_ENTRY`, which gets created while `ROOT_DIR` is locked.
+    file.nlink = 1;
+    Arc::new(RwLock::new(file))
+});
 static ROOT_DIR_ENTRY: LazyLock<DirEntry> = LazyLock::new(|| DirEntry {
     name: "".into(),
     file: ROOT_DIR.clone(),
     filetype: FILETYPE_DIRECTORY,
-    entries: Some(0),
+    entries: Some(ROOT_DIR_ENTRIES),
     cookie: 0,
+    ino: ROOT_DIR.read().ino,
+});
+static PROCESSES: LazyLock<RwLock<HashMap<Pid, Arc<Process>>>> = LazyLock::new(|| {
+    let mut map = HashMap::new();
+    map.insert(0, Arc::new(Process::host()));
+    RwLock::new(map)
 });
-static FD_TABLE: LazyLock<RwLock<FDTable>> = LazyLock::new(|| RwLock::new(FDTable::init()));
-static CURRENT_DIR: RwLock<String> = RwLock::new(String::new());
+static NEXT_PID: RwLock<Pid> = RwLock::new(1);
+static UMASK: RwLock<u32> = RwLock::new(0o022);
+
+/// Creates `/dev` and the devices in it.
+fn dev_dir_entry(ents: &mut DirEntries) -> DirEntry {
+    let entries = next_dir_entries_key();
+    let devices = vec![
+        ("null", CharDevice::Null),
+        ("zero", CharDevice::Zero),
+        ("urandom", CharDevice::Urandom),
+        ("stdin", CharDevice::Stdin),
+        ("stdout", CharDevice::Stdout),
+        ("stderr", CharDevice::Stderr),
+    ];
+    ents.insert(entries, DirEntryList::new());
+    let dir = Dir::new(entries, false);
+    for (name, dev) in devices {
+        let file = Arc::new(RwLock::new(File::new(FileKind::CharDevice(dev))));
+        dir.push(DirEntry::new(name.into(), file), ents);
+    }
+    let dir = Arc::new(RwLock::new(File::new(FileKind::Dir(dir))));
+    DirEntry::new("dev".into(), dir)
+}
 
-fn next_fd() -> Fd {
-    let mut lock = NEXT_FD.write();
+fn next_pid() -> Pid {
+    let mut lock = NEXT_PID.write();
     let next = *lock;
     *lock += 1;
     next
 }
 
-fn next_dir_entry_cookie() -> u64 {
-    let mut lock = NEXT_DIR_ENTRY_COOKIE.write();
+fn next_ino() -> u64 {
+    let mut lock = NEXT_INO.write();
     let next = *lock;
     *lock += 1;
     next
 }
 
+fn umask() -> u32 {
+    *UMASK.read()
+}
+
 fn next_dir_entries_key() -> DirEntriesKey {
     let mut lock = NEXT_DIR_ENTRIES_KEY.write();
     let next = *lock;
@@ -84,6 +136,13 @@ impl Arg {
         }
     }
 
+    fn as_i64(&self) -> i64 {
+        match self {
+            Self::U64(x) => *x as i64,
+            _ => unreachable!(),
+        }
+    }
+
     fn as_str(&self) -> &str {
         match self {
             Self::String(x) => x,
@@ -105,6 +164,14 @@ impl Arg {
         }
     }
 
+    fn as_opt_u32(&self) -> Option<u32> {
+        match self {
+            Self::U32(x) => Some(*x),
+            Self::Null => None,
+            _ => unreachable!(),
+        }
+    }
+
     fn as_opt_u64(&self) -> Option<u64> {
         match self {
             Self::U64(x) => Some(*x),
@@ -170,6 +237,22 @@ fn read_string(ptr: *mut u8, len: usize) -> String {
     unsafe { String::from_utf8_unchecked(bytes) }
 }
 
+/// Copies `buf` from `pos` into the host's buffer.
+fn read_buf(buf: &[u8], pos: u64) -> Result<u64> {
+    let mut nread = MaybeUninit::uninit();
+    let ok = unsafe { read(buf.as_ptr(), buf.len(), pos, nread.as_mut_ptr()) };
+    if ok == 0 {
+        let nread = unsafe { nread.assume_init() };
+        return Ok(nread);
+    } else {
+        return Err(ERRNO_IO);
+    }
+
+    extern "C" {
+        fn read(ptr: *const u8, len: usize, pos: u64, nread_ptr: *mut u64) -> u32;
+    }
+}
+
 #[allow(dead_code)]
 #[derive(Debug)]
 #[repr(u32)]
@@ -196,35 +279,91 @@ enum Request {
     LstatSync,
     Mount,
     Chdir,
-    CWD,
+    Cwd,
+    SymlinkSync,
+    ReadlinkSync,
+    StatSync,
+    LinkSync,
+    UtimesSync,
+    FutimesSync,
+    ChmodSync,
+    AccessSync,
+    Umask,
+    MkfifoSync,
+    FdstatSync,
+    FdstatSetRightsSync,
+    FdstatSetFlagsSync,
+    SetMaxOpenFiles,
+    FdUsage,
+    DupSync,
+    Dup2Sync,
+    CreateProcess,
+    DestroyProcess,
+    PreopenSync,
+    ExitProcess,
+    StdinWriteSync,
+    StdinCloseSync,
+    StdioReadSync,
+    LockSync,
+    UnlockSync,
+    CopySync,
+    FindSync,
 }
 
 #[no_mangle]
-extern "C" fn request(req: Request, args_ptr: *mut u8, args_len: usize) -> Errno {
+extern "C" fn request(pid: Pid, req: Request, args_ptr: *mut u8, args_len: usize) -> Errno {
     let args = if args_ptr.is_null() {
         Vec::new()
     } else {
         read_args(args_ptr, args_len)
     };
-    return match request_impl(req, args) {
+    return match request_impl(pid, req, args) {
         Err(errno) => errno,
         Ok(_) => ERRNO_SUCCESS,
     };
 
-    fn request_impl(req: Request, args: Vec<Arg>) -> Result<()> {
+    fn request_impl(pid: Pid, req: Request, args: Vec<Arg>) -> Result<()> {
+        let process = PROCESSES.read().get(&pid).cloned().ok_or(ERRNO_SRCH)?;
         match req {
             Request::ReadSync => {
                 let fd = args[0].as_u32();
-                let pos = args[1].as_opt_u64();
-
-                let fd_table = FD_TABLE.read();
+                let len = args[1].as_u64() as usize;
+                let pos = args[2].as_opt_u64();
 
-                let mut desc = fd_table.get(fd)?.write();
-                let nread = desc
-                    .file
-                    .read()
-                    .as_regular_file()?
-                    .read(pos.unwrap_or(desc.pos))?;
+                let fd_table = process.fd_table.read();
+
+                fd_table.get(fd)?.read().check(R_OK, RIGHTS_FD_READ)?;
+                let desc = fd_table.get_io(fd)?;
+                let fifo = {
+                    // Bound once, since taking the lock again while holding it can deadlock
+                    // once a writer is waiting.
+                    let desc = desc.read();
+                    match &desc.fifo {
+                        Some(end) => Some((end.reader()?.clone(), desc.nonblock())),
+                        None => None,
+                    }
+                };
+                if pos.is_some() {
+                    // Like `FileDesc::seek`, pipes have no position to read at.
+                    if fifo.is_some() {
+                        return Err(ERRNO_SPIPE);
+                    }
+                    fd_table.get(fd)?.read().check(0, RIGHTS_FD_SEEK)?;
+                }
+                if let Some((fifo, nonblock)) = fifo {
+                    // Reading can block until another thread writes, so don't hold any locks.
+                    drop(fd_table);
+                    let nread = fifo.read(len, nonblock)?;
+                    out(format!("{nread}"));
+                    return Ok(());
+                }
+                let mut desc = desc.write();
+                let nread = {
+                    let file = desc.file.read();
+                    let nread = file.read(len, pos.unwrap_or(desc.pos))?;
+                    file.accessed();
+                    nread
+                };
                 if pos.is_none() {
                     desc.pos += nread;
                 }
@@ -235,85 +374,218 @@ extern "C" fn request(req: Request, args_ptr: *mut u8, args_len: usize) -> Errno
                 let len = args[1].as_u64();
                 let pos = args[2].as_opt_u64();
 
-                let fd_table = FD_TABLE.read();
-
-                let mut desc = fd_table.get(fd)?.write();
-                let nwritten = desc
-                    .file
-                    .write()
-                    .as_regular_file_mut()?
-                    .write(len, pos.unwrap_or(desc.pos))?;
+                let fd_table = process.fd_table.read();
+
+                fd_table.get(fd)?.read().check(W_OK, RIGHTS_FD_WRITE)?;
+                let desc = fd_table.get_io(fd)?;
+                let fifo = {
+                    // Bound once, since taking the lock again while holding it can deadlock
+                    // once a writer is waiting.
+                    let desc = desc.read();
+                    match &desc.fifo {
+                        Some(end) => Some((end.writer()?.clone(), desc.nonblock())),
+                        None => None,
+                    }
+                };
+                if pos.is_some() {
+                    // Like `FileDesc::seek`, pipes have no position to write at.
+                    if fifo.is_some() {
+                        return Err(ERRNO_SPIPE);
+                    }
+                    fd_table.get(fd)?.read().check(0, RIGHTS_FD_SEEK)?;
+                }
+                if let Some((fifo, nonblock)) = fifo {
+                    // Writing can block until another thread reads, so don't hold any locks.
+                    drop(fd_table);
+                    let nwritten = fifo.write(len, nonblock)?;
+                    out(format!("{nwritten}"));
+                    return Ok(());
+                }
+                let mut desc = desc.write();
+                let (write_pos, nwritten) = {
+                    let mut file = desc.file.write();
+                    // Finding the end under the file's lock keeps appends from different
+                    // descriptors from overwriting each other.
+                    let write_pos = match pos {
+                        Some(pos) => pos,
+                        None if desc.append() => file.size(),
+                        None => desc.pos,
+                    };
+                    let nwritten = file.write(len, write_pos)?;
+                    file.modified();
+                    (write_pos, nwritten)
+                };
                 if pos.is_none() {
-                    desc.pos += nwritten;
+                    desc.pos = write_pos + nwritten;
                 }
                 out(format!("{nwritten}"));
             }
             Request::FstatSync => {
                 let fd = args[0].as_u32();
 
-                let fd_table = FD_TABLE.read();
+                let fd_table = process.fd_table.read();
 
                 let desc = fd_table.get(fd)?.read();
                 out(ser_stats(&desc.file.read()));
             }
             Request::OpenSync => {
-                let path = args[0].as_str();
-                let oflags = args[1].as_u32() as Oflags;
-                let fdflags = args[2].as_u32() as Fdflags;
+                let dir_fd = args[0].as_opt_u32();
+                let path = args[1].as_str();
+                let oflags = args[2].as_u32() as Oflags;
+                let fdflags = args[3].as_u32() as Fdflags;
+                let mut rights = args[4].as_u64() as Rights;
+                let mut rights_inheriting = args[5].as_u64() as Rights;
+
+                let mut dir_rights = RIGHTS_PATH_OPEN;
+                if (oflags & OFLAGS_CREAT) != 0 {
+                    dir_rights |= RIGHTS_PATH_CREATE_FILE;
+                }
+                if (oflags & OFLAGS_TRUNC) != 0 {
+                    dir_rights |= RIGHTS_PATH_FILESTAT_SET_SIZE;
+                }
 
                 let mut ents = DIR_ENTRIES.write();
-                let mut fd_table = FD_TABLE.write();
+
+                let (path, root) = process.path_at(dir_fd, path, dir_rights, &ents)?;
+                // What's opened through a dir stays confined to the same preopen.
+                let mut root_entries = ROOT_DIR_ENTRIES;
+                let mut escape = RootEscape::Deny;
+                if let Some(dir_fd) = dir_fd {
+                    // Like WASI, the new descriptor can't have rights the dir doesn't pass on.
+                    // libc and Rust ask for every right that might come in handy, so the rest
+                    // are just left out, and using them fails later on.
+                    let fd_table = process.fd_table.read();
+                    let dir = fd_table.get(dir_fd)?.read();
+                    rights &= dir.rights_inheriting;
+                    rights_inheriting &= dir.rights_inheriting;
+                    root_entries = dir.root;
+                    escape = dir.escape;
+                }
+
+                let mut fd_table = process.fd_table.write();
                 let root_dir = ROOT_DIR.read();
 
                 let file = root_dir
                     .as_dir()
                     .unwrap()
-                    .open(path, oflags, &mut ents)?
+                    .confine(root.as_ref())
+                    .open(&path, oflags, rights, &mut ents)?
                     .unwrap_or_else(|| ROOT_DIR.clone());
-                let fd = fd_table.open(file, fdflags);
+                let fd = fd_table.open(
+                    file,
+                    root_entries,
+                    escape,
+                    fdflags,
+                    rights,
+                    rights_inheriting,
+                )?;
                 out(format!("{fd}"));
             }
             Request::CloseSync => {
                 let fd = args[0].as_u32();
 
-                let mut fd_table = FD_TABLE.write();
+                let mut fd_table = process.fd_table.write();
 
                 fd_table.close(fd)?;
             }
             Request::ReaddirSync => {
-                let path = args[0].as_str();
+                let path = &process.path(args[0].as_str())?;
                 let with_file_types = args[1].as_bool();
+                let recursive = args[2].as_bool();
 
                 let ents = DIR_ENTRIES.read();
                 let root_dir = ROOT_DIR.read();
 
-                let file = root_dir.as_dir().unwrap().lookup(path, &ents)?.file.read();
-                let dirents = file.as_dir()?.entries(&ents);
-                out(ser_dirents(dirents, with_file_types, None));
+                let file = root_dir
+                    .as_dir()
+                    .unwrap()
+                    .lookup(path, true, &ents)?
+                    .file
+                    .read();
+                let dirents = file.as_dir()?.entries(&ents)?;
+                file.check_access(R_OK)?;
+                if recursive {
+                    out(ser_dirents_recursive(dirents, with_file_types, &ents)?);
+                } else {
+                    out(ser_dirents(dirents, with_file_types));
+                }
             }
             Request::RmdirSync => {
-                let path = args[0].as_str();
-                let recursive = args[1].as_bool();
+                let recursive = args[2].as_bool();
 
                 let mut ents = DIR_ENTRIES.write();
                 let root_dir = ROOT_DIR.read();
 
+                let (path, root) = &process.path_at(
+                    args[0].as_opt_u32(),
+                    args[1].as_str(),
+                    RIGHTS_PATH_REMOVE_DIRECTORY,
+                    &ents,
+                )?;
+
                 root_dir
                     .as_dir()
                     .unwrap()
+                    .confine(root.as_ref())
                     .rmdir(path, recursive, &mut ents)?;
             }
             Request::UnlinkSync => {
-                let path = args[0].as_str();
-
                 let mut ents = DIR_ENTRIES.write();
                 let root_dir = ROOT_DIR.read();
 
-                root_dir.as_dir().unwrap().unlink(path, &mut ents)?;
+                let (path, root) = &process.path_at(
+                    args[0].as_opt_u32(),
+                    args[1].as_str(),
+                    RIGHTS_PATH_UNLINK_FILE,
+                    &ents,
+                )?;
+
+                root_dir
+                    .as_dir()
+                    .unwrap()
+                    .confine(root.as_ref())
+                    .unlink(path, &mut ents)?;
             }
             Request::RenameSync => {
-                let old_path = args[0].as_str();
-                let new_path = args[1].as_str();
+                let mut ents = DIR_ENTRIES.write();
+                let root_dir = ROOT_DIR.read();
+
+                let (old_path, old_root) = &process.path_at(
+                    args[0].as_opt_u32(),
+                    args[1].as_str(),
+                    RIGHTS_PATH_RENAME_SOURCE,
+                    &ents,
+                )?;
+                let (new_path, new_root) = &process.path_at(
+                    args[2].as_opt_u32(),
+                    args[3].as_str(),
+                    RIGHTS_PATH_RENAME_TARGET,
+                    &ents,
+                )?;
+
+                let root_dir = root_dir.as_dir().unwrap();
+                root_dir.confine(old_root.as_ref()).rename(
+                    old_path,
+                    &root_dir.confine(new_root.as_ref()),
+                    new_path,
+                    args[4].as_opt_u32().unwrap_or(0),
+                    &mut ents,
+                )?;
+            }
+            Request::CopySync => {
+                let src = &process.path(args[0].as_str())?;
+                let dest = &process.path(args[1].as_str())?;
+                let overwrite = match args[2].as_u32() {
+                    0 => Overwrite::Never,
+                    1 => Overwrite::Skip,
+                    2 => Overwrite::Replace,
+                    _ => return Err(ERRNO_INVAL),
+                };
+                let opts = CopyOptions {
+                    overwrite,
+                    preserve: args[3].as_bool(),
+                    follow: args[4].as_bool(),
+                };
 
                 let mut ents = DIR_ENTRIES.write();
                 let root_dir = ROOT_DIR.read();
@@ -321,10 +593,52 @@ extern "C" fn request(req: Request, args_ptr: *mut u8, args_len: usize) -> Errno
                 root_dir
                     .as_dir()
                     .unwrap()
-                    .rename(old_path, new_path, &mut ents)?;
+                    .copy(src, dest, &opts, &mut ents)?;
+            }
+            Request::FindSync => {
+                let path = &process.path(args[0].as_str())?;
+                // Patterns are separated by NULs, which can't be in paths.
+                let globs = |arg: &Arg| -> Result<Vec<Glob>> {
+                    arg.as_opt_str()
+                        .map_or(Ok(Vec::new()), |s| s.split('\0').map(Glob::new).collect())
+                };
+                let filetype = match args[3].as_opt_u32() {
+                    Some(raw) => Some(
+                        [
+                            FILETYPE_REGULAR_FILE,
+                            FILETYPE_DIRECTORY,
+                            FILETYPE_SYMBOLIC_LINK,
+                            FILETYPE_CHARACTER_DEVICE,
+                            // FIFOs.
+                            FILETYPE_UNKNOWN,
+                        ]
+                        .iter()
+                        .copied()
+                        .find(|filetype| filetype.raw() as u32 == raw)
+                        .ok_or(ERRNO_INVAL)?,
+                    ),
+                    None => None,
+                };
+                let opts = FindOptions {
+                    include: globs(&args[1])?,
+                    exclude: globs(&args[2])?,
+                    filetype,
+                    min_size: args[4].as_opt_u64(),
+                    max_size: args[5].as_opt_u64(),
+                    min_depth: args[6].as_opt_u32().unwrap_or(0) as usize,
+                    max_depth: args[7].as_opt_u32().map(|max| max as usize),
+                    modified_after: args[8].as_opt_u64(),
+                    modified_before: args[9].as_opt_u64(),
+                };
+
+                let ents = DIR_ENTRIES.read();
+                let root_dir = ROOT_DIR.read();
+
+                let found = root_dir.as_dir().unwrap().find(path, &opts, &ents)?;
+                out(ser_found(&found));
             }
             Request::WriteFileSync => {
-                let path = args[0].as_str();
+                let path = &process.path(args[0].as_str())?;
                 let buf_len = args[1].as_usize();
                 let url = args[2].as_opt_str();
 
@@ -337,40 +651,61 @@ extern "C" fn request(req: Request, args_ptr: *mut u8, args_len: usize) -> Errno
                     .write_file(path, buf_len, url, &mut ents)?;
             }
             Request::MkdirSync => {
-                let path = args[0].as_str();
+                let recursive = args[2].as_bool();
 
                 let mut ents = DIR_ENTRIES.write();
                 let root_dir = ROOT_DIR.read();
 
-                root_dir.as_dir().unwrap().mkdir(path, &mut ents)?;
+                let (path, root) = &process.path_at(
+                    args[0].as_opt_u32(),
+                    args[1].as_str(),
+                    RIGHTS_PATH_CREATE_DIRECTORY,
+                    &ents,
+                )?;
+
+                root_dir
+                    .as_dir()
+                    .unwrap()
+                    .confine(root.as_ref())
+                    .mkdir(path, recursive, &mut ents)?;
             }
             Request::TruncateSync => {
-                let path = args[0].as_str();
+                let path = &process.path(args[0].as_str())?;
                 let size = args[1].as_u64();
 
                 let ents = DIR_ENTRIES.read();
                 let root_dir = ROOT_DIR.read();
 
-                root_dir
+                let mut file = root_dir
                     .as_dir()
                     .unwrap()
-                    .lookup(path, &ents)?
+                    .lookup(path, true, &ents)?
                     .file
-                    .write()
-                    .as_regular_file_mut()?
-                    .truncate(size)?;
+                    .write();
+                file.check_access(W_OK)?;
+                file.as_regular_file_mut()?.truncate(size)?;
+                file.modified();
             }
             Request::LstatSync => {
-                let path = args[0].as_str();
-
                 let ents = DIR_ENTRIES.read();
                 let root_dir = ROOT_DIR.read();
 
-                let ent = root_dir.as_dir().unwrap().lookup(path, &ents)?;
+                let (path, root) = &process.path_at(
+                    args[0].as_opt_u32(),
+                    args[1].as_str(),
+                    RIGHTS_PATH_FILESTAT_GET,
+                    &ents,
+                )?;
+
+                let ent = root_dir
+                    .as_dir()
+                    .unwrap()
+                    .confine(root.as_ref())
+                    .lookup(path, false, &ents)?;
                 out(ser_stats(&ent.file.read()));
             }
             Request::ReadFile => {
-                let path = args[0].as_str();
+                let path = &process.path(args[0].as_str())?;
 
                 let ents = DIR_ENTRIES.read();
                 let root_dir = ROOT_DIR.read();
@@ -382,28 +717,44 @@ extern "C" fn request(req: Request, args_ptr: *mut u8, args_len: usize) -> Errno
                 let offset = args[1].as_u64();
                 let size = args[2].as_u64();
 
-                let fd_table = FD_TABLE.read();
+                let fd_table = process.fd_table.read();
 
                 let desc = fd_table.get(fd)?.read();
-                desc.file
-                    .write()
-                    .as_regular_file_mut()?
-                    .allocate(offset, size)?;
+                desc.check(W_OK, RIGHTS_FD_ALLOCATE)?;
+                let mut file = desc.file.write();
+                file.as_regular_file_mut()?.allocate(offset, size)?;
+                file.modified();
             }
             Request::FtruncateSync => {
                 let fd = args[0].as_u32();
                 let size = args[1].as_u64();
 
-                let fd_table = FD_TABLE.read();
+                let fd_table = process.fd_table.read();
 
                 let desc = fd_table.get(fd)?.read();
-                desc.file.write().as_regular_file_mut()?.truncate(size)?;
+                desc.check(W_OK, RIGHTS_FD_FILESTAT_SET_SIZE)?;
+                let mut file = desc.file.write();
+                file.as_regular_file_mut()?.truncate(size)?;
+                file.modified();
             }
             Request::PrestatDirNameSync => {
-                let fd = args[0].as_u32();
+                let fd = args[0].as_opt_u32();
 
-                let fd_table = FD_TABLE.read();
+                let fd_table = process.fd_table.read();
 
+                // Without a fd, lists all of them.
+                let fd = if let Some(fd) = fd {
+                    fd
+                } else {
+                    let preopens = fd_table
+                        .preopens()
+                        .iter()
+                        .map(|(fd, name)| format!(r#"{{"fd":{fd},"name":{name:?}}}"#))
+                        .collect::<Vec<_>>()
+                        .join(",");
+                    out(format!("[{preopens}]"));
+                    return Ok(());
+                };
                 let desc = fd_table.get(fd)?.read();
                 if let Some(preopen) = desc.preopen.as_ref() {
                     out(format!("{:?}", preopen));
@@ -415,36 +766,69 @@ extern "C" fn request(req: Request, args_ptr: *mut u8, args_len: usize) -> Errno
                 let from = args[0].as_u32();
                 let to = args[1].as_u32();
 
-                let mut fd_table = FD_TABLE.write();
+                let mut fd_table = process.fd_table.write();
 
                 fd_table.renumber(from, to)?;
             }
+            Request::DupSync => {
+                let fd = args[0].as_u32();
+
+                let mut fd_table = process.fd_table.write();
+
+                let new_fd = fd_table.dup(fd)?;
+                out(format!("{new_fd}"));
+            }
+            Request::Dup2Sync => {
+                let from = args[0].as_u32();
+                let to = args[1].as_u32();
+
+                let mut fd_table = process.fd_table.write();
+
+                let new_fd = fd_table.dup2(from, to)?;
+                out(format!("{new_fd}"));
+            }
             Request::SeekSync => {
                 let fd = args[0].as_u32();
-                let offset = args[1].as_u64();
-                let whence = match args[2].as_u32() {
-                    0 => WHENCE_SET,
-                    1 => WHENCE_CUR,
-                    2 => WHENCE_END,
-                    _ => return Err(ERRNO_INVAL),
-                };
+                let offset = args[1].as_i64();
+                let whence = args[2].as_u32();
 
-                let fd_table = FD_TABLE.read();
+                let fd_table = process.fd_table.read();
 
-                let new_offset = fd_table.get(fd)?.write().seek(offset, whence)?;
+                let mut desc = fd_table.get(fd)?.write();
+                // Like `fd_tell`, which is how WASI libcs ask for the current position.
+                if whence == 1 && offset == 0 {
+                    desc.check(0, RIGHTS_FD_TELL)?;
+                } else {
+                    desc.check(0, RIGHTS_FD_SEEK)?;
+                }
+                let new_offset = match whence {
+                    0 => desc.seek(offset, WHENCE_SET)?,
+                    1 => desc.seek(offset, WHENCE_CUR)?,
+                    2 => desc.seek(offset, WHENCE_END)?,
+                    SEEK_DATA => desc.seek_data(offset, false)?,
+                    SEEK_HOLE => desc.seek_data(offset, true)?,
+                    _ => return Err(ERRNO_INVAL),
+                };
                 out(format!("{new_offset}"));
             }
             Request::FreaddirSync => {
                 let fd = args[0].as_u32();
                 let cookie = args[1].as_u64();
 
-                let fd_table = FD_TABLE.read();
                 let ents = DIR_ENTRIES.read();
+                let fd_table = process.fd_table.read();
 
                 let desc = fd_table.get(fd)?.read();
+                desc.check(R_OK, RIGHTS_FD_READDIR)?;
                 let file = desc.file.read();
-                let dirents = file.as_dir()?.entries(&ents);
-                out(ser_dirents(dirents, true, Some(cookie)));
+                let dir = file.as_dir()?;
+                let dirents = dir.entries(&ents)?;
+                // `..` stays put at the root of a preopen, like `/..` does.
+                let parent_ino = match dirents.parent() {
+                    Some(parent) if dir.key() != desc.root => dir_entry(parent, &ents)?.ino,
+                    _ => file.ino,
+                };
+                out(ser_dirents_from([file.ino, parent_ino], dirents, cookie));
             }
             Request::Mount => {
                 let is_node = args[0].as_bool();
@@ -460,7 +844,7 @@ extern "C" fn request(req: Request, args_ptr: *mut u8, args_len: usize) -> Errno
                         break;
                     }
                     let src = src.unwrap();
-                    let path = iter.next().unwrap();
+                    let path = &process.path(iter.next().unwrap())?;
 
                     root_dir
                         .as_dir()
@@ -471,35 +855,378 @@ extern "C" fn request(req: Request, args_ptr: *mut u8, args_len: usize) -> Errno
             Request::Chdir => {
                 let dir = args[0].as_str();
 
-                *CURRENT_DIR.write() = dir.into();
+                *process.cwd.write() = dir.into();
+            }
+            Request::Cwd => {
+                out(format!("{:?}", process.cwd.read()));
+            }
+            Request::SymlinkSync => {
+                let target = args[0].as_str();
+
+                let mut ents = DIR_ENTRIES.write();
+                let root_dir = ROOT_DIR.read();
+
+                let (path, root) = &process.path_at(
+                    args[1].as_opt_u32(),
+                    args[2].as_str(),
+                    RIGHTS_PATH_SYMLINK,
+                    &ents,
+                )?;
+
+                root_dir
+                    .as_dir()
+                    .unwrap()
+                    .confine(root.as_ref())
+                    .symlink(target, path, &mut ents)?;
+            }
+            Request::ReadlinkSync => {
+                let ents = DIR_ENTRIES.read();
+                let root_dir = ROOT_DIR.read();
+
+                let (path, root) = &process.path_at(
+                    args[0].as_opt_u32(),
+                    args[1].as_str(),
+                    RIGHTS_PATH_READLINK,
+                    &ents,
+                )?;
+
+                let target = root_dir
+                    .as_dir()
+                    .unwrap()
+                    .confine(root.as_ref())
+                    .readlink(path, &ents)?;
+                out(format!("{target:?}"));
+            }
+            Request::StatSync => {
+                let ents = DIR_ENTRIES.read();
+                let root_dir = ROOT_DIR.read();
+
+                let (path, root) = &process.path_at(
+                    args[0].as_opt_u32(),
+                    args[1].as_str(),
+                    RIGHTS_PATH_FILESTAT_GET,
+                    &ents,
+                )?;
+
+                let ent = root_dir
+                    .as_dir()
+                    .unwrap()
+                    .confine(root.as_ref())
+                    .lookup(path, true, &ents)?;
+                out(ser_stats(&ent.file.read()));
+            }
+            Request::LinkSync => {
+                let follow = args[4].as_bool();
+
+                let mut ents = DIR_ENTRIES.write();
+                let root_dir = ROOT_DIR.read();
+
+                let (old_path, old_root) = &process.path_at(
+                    args[0].as_opt_u32(),
+                    args[1].as_str(),
+                    RIGHTS_PATH_LINK_SOURCE,
+                    &ents,
+                )?;
+                let (new_path, new_root) = &process.path_at(
+                    args[2].as_opt_u32(),
+                    args[3].as_str(),
+                    RIGHTS_PATH_LINK_TARGET,
+                    &ents,
+                )?;
+
+                let root_dir = root_dir.as_dir().unwrap();
+                root_dir.confine(old_root.as_ref()).link(
+                    old_path,
+                    &root_dir.confine(new_root.as_ref()),
+                    new_path,
+                    follow,
+                    &mut ents,
+                )?;
+            }
+            Request::UtimesSync => {
+                let atim = args[2].as_u64();
+                let mtim = args[3].as_u64();
+                let fst_flags = args[4].as_u32() as Fstflags;
+                let follow = args[5].as_bool();
+
+                let ents = DIR_ENTRIES.read();
+                let root_dir = ROOT_DIR.read();
+
+                let (path, root) = &process.path_at(
+                    args[0].as_opt_u32(),
+                    args[1].as_str(),
+                    RIGHTS_PATH_FILESTAT_SET_TIMES,
+                    &ents,
+                )?;
+
+                let ent = root_dir
+                    .as_dir()
+                    .unwrap()
+                    .confine(root.as_ref())
+                    .lookup(path, follow, &ents)?;
+                ent.file.read().set_times(atim, mtim, fst_flags)?;
+            }
+            Request::FutimesSync => {
+                let fd = args[0].as_u32();
+                let atim = args[1].as_u64();
+                let mtim = args[2].as_u64();
+                let fst_flags = args[3].as_u32() as Fstflags;
+
+                let fd_table = process.fd_table.read();
+
+                let desc = fd_table.get(fd)?.read();
+                desc.check(0, RIGHTS_FD_FILESTAT_SET_TIMES)?;
+                desc.file.read().set_times(atim, mtim, fst_flags)?;
+            }
+            Request::ChmodSync => {
+                let path = &process.path(args[0].as_str())?;
+                let mode = args[1].as_u32();
+                let follow = args[2].as_bool();
+
+                let ents = DIR_ENTRIES.read();
+                let root_dir = ROOT_DIR.read();
+
+                let ent = root_dir.as_dir().unwrap().lookup(path, follow, &ents)?;
+                ent.file.read().set_mode(mode);
+            }
+            Request::AccessSync => {
+                let path = &process.path(args[0].as_str())?;
+                let mode = args[1].as_u32();
+
+                let ents = DIR_ENTRIES.read();
+                let root_dir = ROOT_DIR.read();
+
+                let ent = root_dir.as_dir().unwrap().lookup(path, true, &ents)?;
+                ent.file.read().check_access(mode)?;
+            }
+            Request::MkfifoSync => {
+                let path = &process.path(args[0].as_str())?;
+
+                let mut ents = DIR_ENTRIES.write();
+                let root_dir = ROOT_DIR.read();
+
+                root_dir.as_dir().unwrap().mkfifo(path, &mut ents)?;
+            }
+            Request::FdstatSync => {
+                let fd = args[0].as_u32();
+
+                let fd_table = process.fd_table.read();
+
+                let desc = fd_table.get(fd)?.read();
+                let filetype = desc.file.read().filetype().raw();
+                let flags = desc.flags;
+                let rights = desc.rights;
+                let rights_inheriting = desc.rights_inheriting;
+                out(format!(
+                    r#"{{"filetype":{filetype},"flags":{flags},"rights":{rights},"rights_inheriting":{rights_inheriting}}}"#
+                ));
+            }
+            Request::FdstatSetRightsSync => {
+                let fd = args[0].as_u32();
+                let rights = args[1].as_u64() as Rights;
+                let rights_inheriting = args[2].as_u64() as Rights;
+
+                let fd_table = process.fd_table.read();
+
+                fd_table
+                    .get(fd)?
+                    .write()
+                    .set_rights(rights, rights_inheriting)?;
+            }
+            Request::FdstatSetFlagsSync => {
+                let fd = args[0].as_u32();
+                let flags = args[1].as_u32() as Fdflags;
+
+                let fd_table = process.fd_table.read();
+
+                fd_table.get(fd)?.write().set_flags(flags)?;
+            }
+            Request::SetMaxOpenFiles => {
+                let max = args[0].as_usize();
+
+                let mut fd_table = process.fd_table.write();
+
+                fd_table.set_max_open_files(max);
+            }
+            Request::FdUsage => {
+                let fd_table = process.fd_table.read();
+
+                let fds = fd_table
+                    .fds()
+                    .iter()
+                    .map(|fd| fd.to_string())
+                    .collect::<Vec<_>>()
+                    .join(",");
+                let max = fd_table.max_open_files();
+                out(format!(r#"{{"fds":[{fds}],"max":{max}}}"#));
+            }
+            Request::PreopenSync => {
+                let path = &process.path(args[0].as_str())?;
+                let name = args[1].as_str();
+                let rights = args[2].as_u64() as Rights;
+                let escape = if args[3].as_bool() {
+                    RootEscape::Clamp
+                } else {
+                    RootEscape::Deny
+                };
+
+                let ents = DIR_ENTRIES.read();
+                let mut fd_table = process.fd_table.write();
+                let root_dir = ROOT_DIR.read();
+
+                // It has to be the dir itself rather than a symlink to it.
+                let ent = root_dir.as_dir().unwrap().lookup(path, false, &ents)?;
+                if !ent.is_dir() {
+                    return Err(ERRNO_NOTDIR);
+                }
+                let fd = fd_table.preopen(ent.file.clone(), name.into(), rights, escape)?;
+                out(format!("{fd}"));
+            }
+            Request::CreateProcess => {
+                let child = Arc::new(process.spawn()?);
+
+                let child_pid = next_pid();
+                PROCESSES.write().insert(child_pid, child);
+                out(format!("{child_pid}"));
+            }
+            Request::DestroyProcess => {
+                // The host lives as long as the file system.
+                if pid == 0 {
+                    return Err(ERRNO_INVAL);
+                }
+                // Closes whatever the process left open once the last request using it is
+                // done.
+                PROCESSES.write().remove(&pid);
+            }
+            Request::LockSync => {
+                let fd = args[0].as_u32();
+                let exclusive = args[1].as_bool();
+                let start = args[2].as_u64();
+                let len = args[3].as_u64();
+                let nonblock = args[4].as_bool();
+
+                let fd_table = process.fd_table.read();
+
+                let desc = fd_table.get(fd)?.clone();
+                // Like `fcntl`, a shared lock needs the file open for reading and an
+                // exclusive one for writing.
+                desc.read().check(if exclusive { W_OK } else { R_OK }, 0)?;
+                let (owner, locks) = {
+                    let desc = desc.read();
+                    let locks = desc.file.read().locks.clone();
+                    (desc.lock_owner, locks)
+                };
+                // Locking can block until another thread unlocks, so don't hold any locks.
+                // Holding on to `desc` means that if it gets closed meanwhile, the lock is
+                // released when it's dropped below.
+                drop(fd_table);
+                locks.lock(owner, exclusive, start, len, nonblock)?;
+            }
+            Request::UnlockSync => {
+                let fd = args[0].as_u32();
+                let start = args[1].as_u64();
+                let len = args[2].as_u64();
+
+                let fd_table = process.fd_table.read();
+
+                let desc = fd_table.get(fd)?.read();
+                let locks = desc.file.read().locks.clone();
+                locks.unlock(desc.lock_owner, start, len)?;
+            }
+            Request::ExitProcess => {
+                // Like a zombie, the process sticks around until it's destroyed, so the host
+                // can still read what it left in its stdout and stderr.
+                process.fd_table.write().close_all();
+            }
+            Request::StdinWriteSync => {
+                let len = args[0].as_u64();
+
+                let stdio = process.stdio.read();
+                let stdin = stdio.stdin.as_ref().ok_or(ERRNO_BADF)?.writer()?.clone();
+                drop(stdio);
+
+                // Waits for the process to read, on a worker of its own (see
+                // `WorkerPool::requestStdio`). Only writes part of it if the process closes
+                // its stdin meanwhile.
+                let nwritten = stdin.write(len, false)?;
+                out(format!("{nwritten}"));
+            }
+            Request::StdinCloseSync => {
+                process.stdio.write().stdin = None;
+            }
+            Request::StdioReadSync => {
+                let fd = args[0].as_u32();
+                let len = args[1].as_u64() as usize;
+
+                let stdio = process.stdio.read();
+                let pipe = match fd {
+                    1 => &stdio.stdout,
+                    2 => &stdio.stderr,
+                    _ => return Err(ERRNO_BADF),
+                }
+                .as_ref()
+                .ok_or(ERRNO_BADF)?
+                .reader()?
+                .clone();
+                drop(stdio);
+
+                // Waits for the process to write, like `StdinWriteSync`. Reads 0 once the
+                // process has closed its end.
+                let nread = pipe.read(len, false)?;
+                out(format!("{nread}"));
             }
-            Request::CWD => {
-                out(format!("{:?}", CURRENT_DIR.read()));
+            Request::Umask => {
+                let mask = args[0].as_u32();
+
+                let mut umask = UMASK.write();
+                out(format!("{}", *umask));
+                *umask = mask & 0o777;
             }
         }
         Ok(())
     }
 }
 
+// Linux's values for the whences that WASI doesn't have.
+const SEEK_DATA: u32 = 3;
+const SEEK_HOLE: u32 = 4;
+
+// Every file lives on the same virtual device.
+const DEV: u64 = 1;
+
 fn ser_stats(file: &File) -> String {
+    let dev = DEV;
+    let ino = file.ino;
     let size = file.size();
     let filetype = file.filetype().raw();
-    format!(r#"{{"size":{size},"filetype":{filetype}}}"#)
+    let nlink = file.nlink;
+    let mode = file.mode();
+    let atim = file.atim();
+    let mtim = file.mtim();
+    let ctim = file.ctim();
+    // Timestamps are strings since nanoseconds don't fit in a JS number.
+    format!(
+        r#"{{"dev":{dev},"ino":{ino},"size":{size},"filetype":{filetype},"nlink":{nlink},"mode":{mode},"atim":"{atim}","mtim":"{mtim}","ctim":"{ctim}"}}"#
+    )
 }
 
-fn ser_dirents(dirents: &[DirEntry], with_file_types: bool, cookie: Option<u64>) -> String {
+fn ser_found(found: &[(String, Arc<RwLock<File>>)]) -> String {
     let mut ser = Vec::new();
-    for ent in dirents {
-        if cookie.map(|cookie| cookie < ent.cookie).unwrap_or(false) {
-            continue;
-        }
+    for (path, file) in found {
+        ser.push(format!(
+            r#"{{"path":{:?},"stats":{}}}"#,
+            path,
+            ser_stats(&file.read())
+        ));
+    }
+    format!("[{}]", ser.join(","))
+}
+
+fn ser_dirents(dirents: &DirEntryList, with_file_types: bool) -> String {
+    let mut ser = Vec::new();
+    for ent in dirents.iter() {
         if with_file_types {
-            ser.push(format!(
-                r#"{{"name":{:?},"type":{},"cookie":{}}}"#,
-                ent.name,
-                ent.filetype.raw(),
-                ent.cookie
-            ));
+            ser.push(ser_dirent(&ent.name, ent.filetype, ent.cookie, ent.ino));
         } else {
             ser.push(format!("{:?}", ent.name));
         }
@@ -507,6 +1234,69 @@ fn ser_dirents(dirents: &[DirEntry], with_file_types: bool, cookie: Option<u64>)
     format!("[{}]", ser.join(","))
 }
 
+/// Lists what's in `dirents` and in the dirs in it, depth first and by their paths
+/// relative to it, like Node's `readdir` with `recursive`. Symlinks aren't followed.
+fn ser_dirents_recursive(
+    dirents: &DirEntryList,
+    with_file_types: bool,
+    ents: &DirEntries,
+) -> Result<String> {
+    let mut ser = Vec::new();
+    walk(dirents, "", with_file_types, ents, &mut ser)?;
+    return Ok(format!("[{}]", ser.join(",")));
+
+    fn walk(
+        dirents: &DirEntryList,
+        prefix: &str,
+        with_file_types: bool,
+        ents: &DirEntries,
+        ser: &mut Vec<String>,
+    ) -> Result<()> {
+        for ent in dirents.iter() {
+            let path = if prefix.is_empty() {
+                ent.name.clone()
+            } else {
+                format!("{}/{}", prefix, ent.name)
+            };
+            if with_file_types {
+                ser.push(ser_dirent(&path, ent.filetype, ent.cookie, ent.ino));
+            } else {
+                ser.push(format!("{:?}", path));
+            }
+            if let Some(entries) = ent.entries {
+                ent.file.read().check_access(R_OK)?;
+                walk(&ents[&entries], &path, with_file_types, ents, ser)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Lists `.` and `..` (given their inos) and then `dirents`, from the one at `cookie` on,
+/// for `fd_readdir`.
+fn ser_dirents_from(dots: [u64; 2], dirents: &DirEntryList, cookie: u64) -> String {
+    let mut ser = Vec::new();
+    for (i, (name, ino)) in [".", ".."].iter().zip(dots.iter()).enumerate() {
+        if i as u64 >= cookie {
+            ser.push(ser_dirent(name, FILETYPE_DIRECTORY, i as u64, *ino));
+        }
+    }
+    for ent in dirents.iter_from(cookie) {
+        ser.push(ser_dirent(&ent.name, ent.filetype, ent.cookie, ent.ino));
+    }
+    format!("[{}]", ser.join(","))
+}
+
+fn ser_dirent(name: &str, filetype: Filetype, cookie: u64, ino: u64) -> String {
+    format!(
+        r#"{{"name":{:?},"type":{},"cookie":{},"ino":{}}}"#,
+        name,
+        filetype.raw(),
+        cookie,
+        ino
+    )
+}
+
 #[allow(dead_code)]
 fn println(s: String) {
     unsafe { println(s.as_ptr(), s.len()) };
@@ -516,6 +1306,15 @@ fn println(s: String) {
     }
 }
 
+/// Nanoseconds since the unix epoch, according to the host.
+fn now() -> u64 {
+    return unsafe { now() };
+
+    extern "C" {
+        fn now() -> u64;
+    }
+}
+
 fn out(s: String) {
     unsafe { out(s.as_ptr(), s.len()) };
 
@@ -529,7 +1328,24 @@ extern "C" fn alloc(len: usize) -> *mut u8 {
     assert!(len != 0);
     unsafe {
         let layout = std::alloc::Layout::from_size_align(len, std::mem::align_of::<u8>()).unwrap();
-        let ptr = std::alloc::alloc(layout);
-        ptr as *mut u8
+        std::alloc::alloc(layout)
+    }
+}
+
+/// Stand-ins for the host's imports, so tests can run natively. Only what they end up
+/// using (or dropping) needs to be here.
+#[cfg(test)]
+mod host {
+    #[no_mangle]
+    extern "C" fn now() -> u64 {
+        0
+    }
+
+    #[no_mangle]
+    extern "C" fn url_free(_url_ptr: *const u8, _url_len: usize) {}
+
+    #[no_mangle]
+    extern "C" fn url_len(_url_ptr: *const u8, _url_len: usize) -> u64 {
+        0
     }
 }
@@ -0,0 +1,226 @@
+use crate::*;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Condvar, Mutex};
+use wasi::*;
+
+/// What holds a lock: an open file description, so descriptors made with `dup` share
+/// their locks and they're released once the last of them is closed. That's how Linux's
+/// open file description locks (`F_OFD_SETLK`) behave, unlike classic `fcntl` locks which
+/// belong to the process.
+pub type LockOwner = u64;
+
+static NEXT_LOCK_OWNER: AtomicU64 = AtomicU64::new(1);
+
+pub fn next_lock_owner() -> LockOwner {
+    NEXT_LOCK_OWNER.fetch_add(1, Ordering::Relaxed)
+}
+
+/// Advisory locks on a file's bytes. They don't keep anyone from reading or writing, only
+/// from taking conflicting locks.
+#[derive(Default)]
+pub struct Locks {
+    held: Mutex<Vec<Lock>>,
+    /// Notified whenever locks are released or downgraded.
+    cond: Condvar,
+}
+
+#[derive(Clone, Copy)]
+struct Lock {
+    owner: LockOwner,
+    exclusive: bool,
+    start: u64,
+    /// Not included. Locks that reach past the end of the file end at `u64::MAX`.
+    end: u64,
+}
+
+impl Lock {
+    fn conflicts(&self, owner: LockOwner, exclusive: bool, start: u64, end: u64) -> bool {
+        self.owner != owner && (self.exclusive || exclusive) && self.start < end && start < self.end
+    }
+}
+
+impl Locks {
+    /// Locks `len` bytes from `start`, or everything from `start` on when `len` is 0 (like
+    /// `fcntl`). Whatever `owner` held in that range is replaced, which is how locks are
+    /// upgraded or downgraded. Blocks until conflicting locks are released unless
+    /// `nonblock` is set, in which case it fails with EAGAIN.
+    pub fn lock(
+        &self,
+        owner: LockOwner,
+        exclusive: bool,
+        start: u64,
+        len: u64,
+        nonblock: bool,
+    ) -> Result<()> {
+        let end = range_end(start, len)?;
+        let mut held = self.held.lock().unwrap();
+        while held
+            .iter()
+            .any(|lock| lock.conflicts(owner, exclusive, start, end))
+        {
+            if nonblock {
+                return Err(ERRNO_AGAIN);
+            }
+            held = self.cond.wait(held).unwrap();
+        }
+        remove(&mut held, owner, start, end);
+        held.push(Lock {
+            owner,
+            exclusive,
+            start,
+            end,
+        });
+        drop(held);
+        // Downgrading to shared can let others in.
+        self.cond.notify_all();
+        Ok(())
+    }
+
+    /// Unlocks a range, see `lock`. Unlocking what isn't locked is fine.
+    pub fn unlock(&self, owner: LockOwner, start: u64, len: u64) -> Result<()> {
+        let end = range_end(start, len)?;
+        remove(&mut self.held.lock().unwrap(), owner, start, end);
+        self.cond.notify_all();
+        Ok(())
+    }
+
+    /// Drops everything `owner` holds, for when it's closed.
+    pub fn release(&self, owner: LockOwner) {
+        self.held.lock().unwrap().retain(|lock| lock.owner != owner);
+        self.cond.notify_all();
+    }
+}
+
+fn range_end(start: u64, len: u64) -> Result<u64> {
+    if len == 0 {
+        Ok(u64::MAX)
+    } else {
+        start.checked_add(len).ok_or(ERRNO_INVAL)
+    }
+}
+
+/// Carves `start..end` out of `owner`'s locks, splitting the ones that straddle it.
+fn remove(held: &mut Vec<Lock>, owner: LockOwner, start: u64, end: u64) {
+    let mut kept = Vec::with_capacity(held.len());
+    for lock in held.drain(..) {
+        if lock.owner != owner || lock.end <= start || end <= lock.start {
+            kept.push(lock);
+            continue;
+        }
+        if lock.start < start {
+            kept.push(Lock { end: start, ..lock });
+        }
+        if end < lock.end {
+            kept.push(Lock { start: end, ..lock });
+        }
+    }
+    *held = kept;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use parking_lot::RwLock;
+    use std::sync::{mpsc, Arc};
+    use std::thread;
+    use std::time::Duration;
+
+    fn try_lock(locks: &Locks, owner: LockOwner, exclusive: bool, start: u64, len: u64) -> bool {
+        match locks.lock(owner, exclusive, start, len, true) {
+            Ok(()) => true,
+            Err(ERRNO_AGAIN) => false,
+            Err(err) => panic!("unexpected errno {}", err),
+        }
+    }
+
+    #[test]
+    fn conflicts() {
+        let locks = Locks::default();
+        assert!(try_lock(&locks, 1, false, 0, 10));
+        assert!(try_lock(&locks, 2, false, 5, 10));
+        assert!(!try_lock(&locks, 3, true, 9, 1));
+        // Ranges don't include their end.
+        assert!(try_lock(&locks, 3, true, 15, 1));
+        // An owner never conflicts with itself.
+        assert!(try_lock(&locks, 1, true, 0, 5));
+        assert!(!try_lock(&locks, 2, false, 0, 1));
+    }
+
+    #[test]
+    fn to_end_of_file() {
+        let locks = Locks::default();
+        assert!(try_lock(&locks, 1, true, 10, 0));
+        assert!(!try_lock(&locks, 2, false, u64::MAX - 1, 1));
+        assert!(try_lock(&locks, 2, false, 0, 10));
+        assert_eq!(locks.lock(2, false, u64::MAX, 1, true), Err(ERRNO_INVAL));
+    }
+
+    #[test]
+    fn unlock_splits() {
+        let locks = Locks::default();
+        assert!(try_lock(&locks, 1, true, 0, 100));
+        locks.unlock(1, 40, 20).unwrap();
+        assert!(try_lock(&locks, 2, true, 40, 20));
+        assert!(!try_lock(&locks, 3, false, 39, 1));
+        assert!(!try_lock(&locks, 3, false, 60, 1));
+        // Unlocking what isn't locked, or what someone else locked, does nothing.
+        locks.unlock(1, 200, 0).unwrap();
+        locks.unlock(3, 0, 0).unwrap();
+        assert!(!try_lock(&locks, 3, false, 0, 1));
+        locks.unlock(1, 0, 0).unwrap();
+        assert!(try_lock(&locks, 3, false, 0, 40));
+    }
+
+    #[test]
+    fn relock_splits() {
+        let locks = Locks::default();
+        assert!(try_lock(&locks, 1, true, 0, 30));
+        // Downgrading the middle leaves the ends exclusive.
+        assert!(try_lock(&locks, 1, false, 10, 10));
+        assert!(try_lock(&locks, 2, false, 10, 10));
+        assert!(!try_lock(&locks, 2, false, 9, 1));
+        assert!(!try_lock(&locks, 2, false, 20, 1));
+    }
+
+    #[test]
+    fn upgrade_and_downgrade() {
+        let locks = Locks::default();
+        assert!(try_lock(&locks, 1, false, 0, 10));
+        assert!(try_lock(&locks, 2, false, 0, 10));
+        assert!(!try_lock(&locks, 1, true, 0, 10));
+        locks.unlock(2, 0, 10).unwrap();
+        assert!(try_lock(&locks, 1, true, 0, 10));
+        assert!(!try_lock(&locks, 3, false, 0, 10));
+        assert!(try_lock(&locks, 1, false, 0, 10));
+        assert!(try_lock(&locks, 3, false, 0, 10));
+        assert!(!try_lock(&locks, 3, true, 0, 10));
+    }
+
+    #[test]
+    fn waits_for_release() {
+        let locks = Arc::new(Locks::default());
+        assert!(try_lock(&locks, 1, true, 0, 0));
+        let (send, recv) = mpsc::channel();
+        {
+            let locks = locks.clone();
+            thread::spawn(move || send.send(locks.lock(2, true, 0, 0, false)).unwrap());
+        }
+        assert!(recv.recv_timeout(Duration::from_millis(50)).is_err());
+        locks.release(1);
+        recv.recv().unwrap().unwrap();
+        assert!(!try_lock(&locks, 1, false, 0, 1));
+    }
+
+    #[test]
+    fn released_when_desc_dropped() {
+        let file = Arc::new(RwLock::new(File::new(FileKind::RegularFile(
+            RegularFile::Buf(Vec::new()),
+        ))));
+        let locks = file.read().locks.clone();
+        let desc = FileDesc::new(file, 0, None, RIGHTS_FD_WRITE, 0);
+        assert!(try_lock(&locks, desc.lock_owner, true, 0, 0));
+        assert!(!try_lock(&locks, next_lock_owner(), false, 0, 0));
+        drop(desc);
+        assert!(try_lock(&locks, next_lock_owner(), true, 0, 0));
+    }
+}
@@ -0,0 +1,281 @@
+use crate::*;
+use std::fmt;
+use wasi::*;
+
+/// Longest path accepted, counting the NUL that would end it in C (like Linux).
+pub const PATH_MAX: usize = 4096;
+/// Longest file name accepted, in bytes.
+pub const NAME_MAX: usize = 255;
+
+/// What `..` does in the dir a path is confined to (the root dir if it isn't).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RootEscape {
+    /// Stays put, like POSIX does for `/..`.
+    Clamp,
+    /// Fails with ENOTCAPABLE, like WASI does for paths that try to leave a preopen.
+    Deny,
+}
+
+/// The dir paths resolved through a preopen are confined to, see `Dir::confine`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ConfinedRoot {
+    /// Relative to the root dir.
+    pub path: String,
+    /// What `..` does in it.
+    pub escape: RootEscape,
+}
+
+/// A path relative to the root dir without `.` or empty components, e.g. `a/b` (or an
+/// empty string for the root dir itself). `..` is left in, since where it leads depends on
+/// what the components before it turn out to be, see `Dir::resolve_entry`.
+#[derive(Debug, PartialEq, Eq)]
+pub struct NormalPath {
+    pub path: String,
+    /// Set when the path ended in `/`, `/.` or `/..`, so it has to name a dir (following
+    /// a symlink to one is fine).
+    pub dir: bool,
+}
+
+/// Keeps the trailing `/` of paths that have to name a dir, so normalizing the result
+/// again gives the same path.
+impl fmt::Display for NormalPath {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.dir && !self.path.is_empty() {
+            write!(f, "{}/", self.path)
+        } else {
+            write!(f, "{}", self.path)
+        }
+    }
+}
+
+/// Normalizes a path relative to the root dir. It can start with `~/` (or `/`) too, which
+/// stand for the root dir, see `Process::path`. Only its names are checked, since it's
+/// usually been joined onto a cwd or dir already, see `check`.
+pub fn normalize(path: &str) -> Result<NormalPath> {
+    let rest = if path == "~" {
+        ""
+    } else {
+        path.strip_prefix("~/").unwrap_or(path)
+    };
+    check_names(path)?;
+    Ok(resolve("", rest))
+}
+
+/// Like `normalize`, but resolves `path` relative to `base`, which has to be a normalized
+/// path already. `path` is taken as is, so a leading `~` is just a name.
+pub fn join(base: &str, path: &str) -> Result<NormalPath> {
+    check_names(path)?;
+    Ok(resolve(base, path))
+}
+
+/// Whether `path` is `root` or inside of it. Both are relative to the root dir, which
+/// contains everything.
+pub fn is_within(path: &str, root: &str) -> bool {
+    root.is_empty()
+        || path == root
+        || (path.starts_with(root) && path[root.len()..].starts_with("/"))
+}
+
+/// What `..` does in the dir at `path` (where it really is, not through symlinks) when
+/// paths are confined to `root`: `true` when it goes up to the parent, `false` when it
+/// stays put, like POSIX does for `/..`. Leaving `root` fails with ENOTCAPABLE.
+pub fn dot_dot(path: &str, root: &str, escape: RootEscape) -> Result<bool> {
+    if path == root {
+        match escape {
+            RootEscape::Clamp => Ok(false),
+            RootEscape::Deny => Err(ERRNO_NOTCAPABLE),
+        }
+    } else if is_within(path, root) {
+        Ok(true)
+    } else {
+        Err(ERRNO_NOTCAPABLE)
+    }
+}
+
+/// Fails with EINVAL for paths with NUL bytes (which C can't pass), and with ENAMETOOLONG
+/// for paths over `PATH_MAX` or names over `NAME_MAX`. Like POSIX, this goes for paths as
+/// they're passed in, before they're joined onto the cwd or a dir.
+pub fn check(path: &str) -> Result<()> {
+    if path.len() >= PATH_MAX {
+        return Err(ERRNO_NAMETOOLONG);
+    }
+    check_names(path)
+}
+
+fn check_names(path: &str) -> Result<()> {
+    if path.contains('\0') {
+        return Err(ERRNO_INVAL);
+    }
+    if path.split("/").any(|name| name.len() > NAME_MAX) {
+        return Err(ERRNO_NAMETOOLONG);
+    }
+    Ok(())
+}
+
+fn resolve(base: &str, path: &str) -> NormalPath {
+    let resolved = components(base)
+        .chain(components(path))
+        .filter(|name| *name != ".")
+        .collect::<Vec<_>>();
+    NormalPath {
+        path: resolved.join("/"),
+        dir: matches!(path.rsplit("/").next(), Some("" | "." | "..")),
+    }
+}
+
+fn components(path: &str) -> impl Iterator<Item = &str> {
+    path.split("/").filter(|name| !name.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use RootEscape::*;
+
+    fn norm(path: &str) -> Result<String> {
+        normalize(path).map(|path| path.to_string())
+    }
+
+    #[test]
+    fn plain() {
+        assert_eq!(norm("a").unwrap(), "a");
+        assert_eq!(norm("a/b/c").unwrap(), "a/b/c");
+        assert_eq!(norm("").unwrap(), "");
+    }
+
+    #[test]
+    fn empty_and_dot_components() {
+        assert_eq!(norm("a//b").unwrap(), "a/b");
+        assert_eq!(norm("./a/./b").unwrap(), "a/b");
+        assert_eq!(norm("/a").unwrap(), "a");
+        assert_eq!(norm("//a").unwrap(), "a");
+        assert_eq!(norm(".").unwrap(), "");
+        assert_eq!(norm("/").unwrap(), "");
+    }
+
+    #[test]
+    fn dot_dot_is_kept() {
+        assert_eq!(norm("a/b/../c").unwrap(), "a/b/../c");
+        assert_eq!(norm("a/./../b").unwrap(), "a/../b");
+        assert_eq!(norm("a/..").unwrap(), "a/../");
+        assert_eq!(norm("/..").unwrap(), "../");
+        assert_eq!(norm("~/../a").unwrap(), "../a");
+    }
+
+    #[test]
+    fn tilde() {
+        assert_eq!(norm("~").unwrap(), "");
+        assert_eq!(norm("~/a").unwrap(), "a");
+        assert_eq!(norm("~/a/~").unwrap(), "a/~");
+        assert_eq!(norm("~a").unwrap(), "~a");
+        assert_eq!(join("", "~/a").unwrap().path, "~/a");
+    }
+
+    #[test]
+    fn trailing_slash() {
+        let path = normalize("a/b/").unwrap();
+        assert_eq!(path.path, "a/b");
+        assert!(path.dir);
+        assert!(normalize("a/b/.").unwrap().dir);
+        assert!(normalize("a/b/..").unwrap().dir);
+        assert!(normalize("a//").unwrap().dir);
+        assert!(!normalize("a/b").unwrap().dir);
+        assert!(!normalize("a/./b").unwrap().dir);
+        assert!(!normalize("a/../b").unwrap().dir);
+    }
+
+    #[test]
+    fn display_keeps_trailing_slash() {
+        assert_eq!(norm("a/b/").unwrap(), "a/b/");
+        assert_eq!(norm("a/b/.").unwrap(), "a/b/");
+        assert_eq!(norm("a/b").unwrap(), "a/b");
+        assert_eq!(norm("/").unwrap(), "");
+        for path in &["a/b/", "a/b", "a/./c/../"] {
+            let once = normalize(path).unwrap();
+            assert_eq!(normalize(&once.to_string()).unwrap(), once);
+        }
+    }
+
+    #[test]
+    fn nul() {
+        assert_eq!(norm("a\0b"), Err(ERRNO_INVAL));
+        assert_eq!(norm("\0"), Err(ERRNO_INVAL));
+        assert_eq!(join("a", "b/\0"), Err(ERRNO_INVAL));
+    }
+
+    #[test]
+    fn name_too_long() {
+        let name = "a".repeat(NAME_MAX);
+        assert_eq!(norm(&name).unwrap(), name);
+        assert_eq!(norm(&format!("{}/b", name)).unwrap(), format!("{}/b", name));
+        let long = "a".repeat(NAME_MAX + 1);
+        assert_eq!(norm(&long), Err(ERRNO_NAMETOOLONG));
+        assert_eq!(norm(&format!("b/{}/c", long)), Err(ERRNO_NAMETOOLONG));
+    }
+
+    #[test]
+    fn path_too_long() {
+        let path = "a/".repeat((PATH_MAX - 1) / 2);
+        assert_eq!(path.len(), PATH_MAX - 2);
+        assert!(check(&path).is_ok());
+        assert!(check(&format!("{}b", path)).is_ok());
+        assert_eq!(check(&format!("{}bc", path)), Err(ERRNO_NAMETOOLONG));
+        // It's the path as given that counts, not what it normalizes to.
+        let dots = "./".repeat(PATH_MAX / 2);
+        assert_eq!(check(&dots), Err(ERRNO_NAMETOOLONG));
+        assert_eq!(check("a/\0"), Err(ERRNO_INVAL));
+    }
+
+    #[test]
+    fn joined_past_path_max() {
+        // Like a short path in a deep dir, which is only checked before it's joined.
+        let base = "a/".repeat(PATH_MAX / 2);
+        let base = base.trim_end_matches('/');
+        assert!(check("b").is_ok());
+        assert_eq!(join(base, "b").unwrap().path, format!("{}/b", base));
+        assert!(normalize(&format!("~/{}/b", base)).is_ok());
+    }
+
+    #[test]
+    fn dot_dot_goes_up() {
+        assert_eq!(dot_dot("a/b", "", Clamp), Ok(true));
+        assert_eq!(dot_dot("a", "", Deny), Ok(true));
+        assert_eq!(dot_dot("out/a", "out", Deny), Ok(true));
+    }
+
+    #[test]
+    fn dot_dot_at_root() {
+        assert_eq!(dot_dot("", "", Clamp), Ok(false));
+        assert_eq!(dot_dot("", "", Deny), Err(ERRNO_NOTCAPABLE));
+        assert_eq!(dot_dot("out", "out", Clamp), Ok(false));
+        assert_eq!(dot_dot("out", "out", Deny), Err(ERRNO_NOTCAPABLE));
+        // Anywhere else is out of reach already.
+        assert_eq!(dot_dot("other", "out", Clamp), Err(ERRNO_NOTCAPABLE));
+        assert_eq!(dot_dot("", "out", Clamp), Err(ERRNO_NOTCAPABLE));
+    }
+
+    #[test]
+    fn join_relative() {
+        let join = |base, path| join(base, path).map(|path| path.to_string());
+        assert_eq!(join("a", "b").unwrap(), "a/b");
+        assert_eq!(join("a/b", "../c").unwrap(), "a/b/../c");
+        assert_eq!(join("a", "").unwrap(), "a/");
+        assert_eq!(join("a", ".").unwrap(), "a/");
+        assert_eq!(join("", "b/").unwrap(), "b/");
+        // `base` is already normalized, so it isn't checked again.
+        assert_eq!(join("a//b", "c").unwrap(), "a/b/c");
+    }
+
+    #[test]
+    fn within() {
+        assert!(is_within("a", ""));
+        assert!(is_within("", ""));
+        assert!(is_within("a", "a"));
+        assert!(is_within("a/b", "a"));
+        assert!(is_within("a/b/c", "a/b"));
+        assert!(!is_within("ab", "a"));
+        assert!(!is_within("", "a"));
+        assert!(!is_within("b/a", "a"));
+        assert!(!is_within("a", "a/b"));
+    }
+}
@@ -0,0 +1,135 @@
+use crate::*;
+use parking_lot::RwLock;
+use std::sync::Arc;
+use wasi::*;
+
+pub type Pid = u32;
+
+/// A program using the file system. The host is process 0, and the programs it runs get
+/// their own process so they don't share descriptors or a cwd.
+pub struct Process {
+    pub fd_table: RwLock<FDTable>,
+    pub cwd: RwLock<String>,
+    pub stdio: RwLock<Stdio>,
+}
+
+/// The host's ends of the pipes the process has as stdio, so the host can feed it input
+/// and collect its output while it runs.
+#[derive(Default)]
+pub struct Stdio {
+    /// Dropped once the host is done writing, so the process reads EOF.
+    pub stdin: Option<FifoEnd>,
+    pub stdout: Option<FifoEnd>,
+    pub stderr: Option<FifoEnd>,
+}
+
+impl Process {
+    /// `dir` is the cwd, which the guest sees as `/`.
+    pub fn new(cwd: String, dir: Arc<RwLock<File>>) -> Self {
+        let pipes = [Fifo::new(), Fifo::new(), Fifo::new()];
+        let stdio = Stdio {
+            stdin: Some(pipes[0].open(false, true)),
+            stdout: Some(pipes[1].open(true, false)),
+            stderr: Some(pipes[2].open(true, false)),
+        };
+        Self {
+            fd_table: RwLock::new(FDTable::init(dir, &pipes)),
+            cwd: RwLock::new(cwd),
+            stdio: RwLock::new(stdio),
+        }
+    }
+
+    /// The host itself. Nothing reads or writes its end of its stdio, so like with closed
+    /// pipes, its stdin is at EOF, and writing to its stdout or stderr fails with EPIPE
+    /// rather than blocking once they're full.
+    pub fn host() -> Self {
+        let host = Self::new(String::new(), ROOT_DIR.clone());
+        *host.stdio.write() = Stdio::default();
+        host
+    }
+
+    /// A child starts out in its parent's cwd, with the same open-file limit.
+    pub fn spawn(&self) -> Result<Self> {
+        let cwd = self.cwd.read().clone();
+        let dir = {
+            let ents = DIR_ENTRIES.read();
+            let root_dir = ROOT_DIR.read();
+            let ent = root_dir
+                .as_dir()
+                .unwrap()
+                .lookup(&self.path(".")?, true, &ents)?;
+            if !ent.is_dir() {
+                return Err(ERRNO_NOTDIR);
+            }
+            ent.file.clone()
+        };
+        let child = Self::new(cwd, dir);
+        let max_open_files = self.fd_table.read().max_open_files();
+        child.fd_table.write().set_max_open_files(max_open_files);
+        Ok(child)
+    }
+
+    /// Like `path`, but relative to the dir open at `fd` when there is one. That requires
+    /// `fd` to have `rights`. Also returns the dir the path is confined to then, which is
+    /// that of the preopen `fd` came from (see `Dir::confine`). Callers have `DIR_ENTRIES`
+    /// locked already, since it's taken before the descriptors.
+    pub fn path_at(
+        &self,
+        fd: Option<Fd>,
+        path: &str,
+        rights: Rights,
+        ents: &DirEntries,
+    ) -> Result<(String, Option<ConfinedRoot>)> {
+        if let Some(fd) = fd {
+            let fd_table = self.fd_table.read();
+            let desc = fd_table.get(fd)?.read();
+            desc.check(0, rights)?;
+            let (path, root) = desc.resolve(path, ents)?;
+            Ok((path, Some(root)))
+        } else {
+            Ok((self.path(path)?, None))
+        }
+    }
+
+    /// Makes `path` relative to the root dir instead of the cwd, which is what `Dir`
+    /// expects. `path` is checked first, since a deep cwd can take it past `PATH_MAX`.
+    pub fn path(&self, mut path: &str) -> Result<String> {
+        check(path)?;
+        // Hack to get Emscripten absolute/relative paths to "work".
+        while path.starts_with("~/~/") {
+            path = &path[4..];
+        }
+        if path.starts_with("~") {
+            Ok(path.into())
+        } else {
+            Ok(format!("~/{}/{}", self.cwd.read(), path))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn path_in_deep_cwd() {
+        let cwd = "a/".repeat(PATH_MAX / 2);
+        let process = Process::new(cwd.trim_end_matches('/').into(), ROOT_DIR.clone());
+        let path = process.path("b").unwrap();
+        assert!(path.len() > PATH_MAX);
+        assert!(normalize(&path).is_ok());
+        // It's still checked as given.
+        let long = "b/".repeat(PATH_MAX / 2);
+        assert_eq!(process.path(&long), Err(ERRNO_NAMETOOLONG));
+        assert_eq!(process.path("~/b\0"), Err(ERRNO_INVAL));
+    }
+    #[test]
+    fn host_stdio_closed() {
+        let host = Process::host();
+        let fd_table = host.fd_table.read();
+        let stdin = fd_table.get(0).unwrap().read();
+        let stdin = stdin.fifo.as_ref().unwrap().reader().unwrap();
+        // It reads EOF rather than waiting for a write that never comes.
+        assert_eq!(stdin.read(1, false), Ok(0));
+    }
+}
@@ -0,0 +1,249 @@
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+
+/// File contents stored as extents of written data. Everything in between (holes) reads
+/// back as zeros without taking up memory.
+#[derive(Clone)]
+pub struct SparseBuf {
+    /// Keyed by offset. Extents never overlap, touch or reach past `size`.
+    extents: BTreeMap<u64, Vec<u8>>,
+    size: u64,
+}
+
+impl SparseBuf {
+    pub fn new(buf: Vec<u8>) -> Self {
+        let size = buf.len() as u64;
+        let mut extents = BTreeMap::new();
+        if !buf.is_empty() {
+            extents.insert(0, buf);
+        }
+        Self { extents, size }
+    }
+
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// Shrinking drops whatever was past `size`, growing leaves a hole.
+    pub fn set_size(&mut self, size: u64) {
+        if size < self.size {
+            self.extents.split_off(&size);
+            if let Some((offset, extent)) = self.extents.iter_mut().next_back() {
+                let end = offset + extent.len() as u64;
+                if end > size {
+                    extent.truncate((size - offset) as usize);
+                }
+            }
+        }
+        self.size = size;
+    }
+
+    pub fn read(&self, pos: u64, len: usize) -> Vec<u8> {
+        let end = pos.saturating_add(len as u64).min(self.size);
+        if pos >= end {
+            return Vec::new();
+        }
+        let mut buf = vec![0; (end - pos) as usize];
+        let first = self
+            .extents
+            .range(..=pos)
+            .next_back()
+            .map(|(offset, _)| *offset)
+            .unwrap_or(pos);
+        for (offset, extent) in self.extents.range(first..end) {
+            let start = pos.max(*offset);
+            let stop = end.min(offset + extent.len() as u64);
+            if start < stop {
+                buf[(start - pos) as usize..(stop - pos) as usize]
+                    .copy_from_slice(&extent[(start - offset) as usize..(stop - offset) as usize]);
+            }
+        }
+        buf
+    }
+
+    pub fn write(&mut self, pos: u64, data: &[u8]) -> Option<()> {
+        let end = pos.checked_add(data.len() as u64)?;
+        // Like POSIX, writing nothing doesn't grow the file, and it mustn't leave an empty
+        // extent behind.
+        if data.is_empty() {
+            return Some(());
+        }
+
+        // Extend the extent that `pos` falls in (or directly follows), or start a new one.
+        let mut start = pos;
+        let mut extent = Vec::new();
+        if let Some((offset, prev)) = self.extents.range(..=pos).next_back() {
+            if offset + prev.len() as u64 >= pos {
+                start = *offset;
+                extent = self.extents.remove(&start).unwrap();
+            }
+        }
+        let at = usize::try_from(pos - start).ok()?;
+        at.checked_add(data.len())?;
+        if extent.len() < at + data.len() {
+            extent.resize(at + data.len(), 0);
+        }
+        extent[at..at + data.len()].copy_from_slice(data);
+
+        // Swallow the extents that are overwritten or touched by `data`.
+        let next = self
+            .extents
+            .range(pos..=end)
+            .map(|(offset, _)| *offset)
+            .collect::<Vec<_>>();
+        for offset in next {
+            let next = self.extents.remove(&offset).unwrap();
+            let next_end = offset + next.len() as u64;
+            if next_end > end {
+                extent.extend_from_slice(&next[(end - offset) as usize..]);
+            }
+        }
+
+        self.extents.insert(start, extent);
+        self.size = self.size.max(end);
+        Some(())
+    }
+
+    /// The first offset holding data at or after `offset`, for `SEEK_DATA`.
+    pub fn next_data(&self, offset: u64) -> Option<u64> {
+        if offset >= self.size {
+            return None;
+        }
+        if let Some((start, extent)) = self.extents.range(..=offset).next_back() {
+            if start + extent.len() as u64 > offset {
+                return Some(offset);
+            }
+        }
+        self.extents.range(offset..).next().map(|(start, _)| *start)
+    }
+
+    /// The first offset in a hole at or after `offset`, for `SEEK_HOLE`. The end of the
+    /// file counts as a hole.
+    pub fn next_hole(&self, offset: u64) -> Option<u64> {
+        if offset >= self.size {
+            return None;
+        }
+        if let Some((start, extent)) = self.extents.range(..=offset).next_back() {
+            let end = start + extent.len() as u64;
+            if end > offset {
+                return Some(end);
+            }
+        }
+        Some(offset)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Data at 10..12 and 20..22 of 30 bytes, so holes lead, trail and sit in between.
+    fn holey() -> SparseBuf {
+        let mut buf = SparseBuf::new(Vec::new());
+        buf.write(10, b"ab").unwrap();
+        buf.write(20, b"cd").unwrap();
+        buf.set_size(30);
+        buf
+    }
+
+    #[test]
+    fn holes_read_as_zeros() {
+        let buf = holey();
+        let mut expected = vec![0; 30];
+        expected[10..12].copy_from_slice(b"ab");
+        expected[20..22].copy_from_slice(b"cd");
+        assert_eq!(buf.read(0, 30), expected);
+        assert_eq!(buf.read(11, 10), b"b\0\0\0\0\0\0\0\0c");
+        // Reads stop at the end.
+        assert_eq!(buf.read(28, 10), b"\0\0");
+        assert!(buf.read(30, 10).is_empty());
+        assert!(buf.read(100, 10).is_empty());
+    }
+
+    #[test]
+    fn writes_merge() {
+        let mut buf = SparseBuf::new(b"aa".to_vec());
+        buf.write(5, b"bb").unwrap();
+        assert_eq!(buf.next_hole(0), Some(2));
+        // Over the hole and into both extents.
+        buf.write(1, b"xxxxx").unwrap();
+        assert_eq!(buf.read(0, 7), b"axxxxxb");
+        assert_eq!(buf.next_hole(0), Some(7));
+
+        // Ones that only touch merge too.
+        buf.write(10, b"c").unwrap();
+        buf.write(8, b"d").unwrap();
+        buf.write(9, b"e").unwrap();
+        buf.write(7, b"f").unwrap();
+        assert_eq!(buf.read(0, 11), b"axxxxxbfdec");
+        assert_eq!(buf.next_hole(0), Some(11));
+        assert_eq!(buf.size(), 11);
+
+        // Within an extent.
+        buf.write(3, b"yy").unwrap();
+        assert_eq!(buf.read(0, 11), b"axxyyxbfdec");
+        assert_eq!(buf.next_hole(0), Some(11));
+    }
+
+    #[test]
+    fn empty_write() {
+        let mut buf = SparseBuf::new(b"ab".to_vec());
+        buf.write(10, b"").unwrap();
+        assert_eq!(buf.size(), 2);
+        buf.set_size(20);
+        assert_eq!(buf.next_data(2), None);
+    }
+
+    #[test]
+    fn shrink_into_extent() {
+        let mut buf = SparseBuf::new(Vec::new());
+        buf.write(10, b"abcdef").unwrap();
+        buf.write(20, b"gh").unwrap();
+        buf.set_size(13);
+        assert_eq!(buf.size(), 13);
+        assert_eq!(buf.read(10, 10), b"abc");
+        // Growing again leaves zeros where the data was cut.
+        buf.set_size(22);
+        assert_eq!(buf.read(10, 12), b"abc\0\0\0\0\0\0\0\0\0");
+        assert_eq!(buf.next_hole(10), Some(13));
+        assert_eq!(buf.next_data(13), None);
+        // Right at an extent's start drops all of it.
+        buf.set_size(10);
+        assert_eq!(buf.next_data(0), None);
+    }
+
+    #[test]
+    fn next_data() {
+        let buf = holey();
+        assert_eq!(buf.next_data(0), Some(10));
+        assert_eq!(buf.next_data(10), Some(10));
+        assert_eq!(buf.next_data(11), Some(11));
+        assert_eq!(buf.next_data(12), Some(20));
+        assert_eq!(buf.next_data(21), Some(21));
+        // Nothing but the trailing hole is left.
+        assert_eq!(buf.next_data(22), None);
+        assert_eq!(buf.next_data(30), None);
+    }
+
+    #[test]
+    fn next_hole() {
+        let buf = holey();
+        assert_eq!(buf.next_hole(0), Some(0));
+        assert_eq!(buf.next_hole(10), Some(12));
+        assert_eq!(buf.next_hole(15), Some(15));
+        assert_eq!(buf.next_hole(20), Some(22));
+        assert_eq!(buf.next_hole(29), Some(29));
+        assert_eq!(buf.next_hole(30), None);
+    }
+
+    #[test]
+    fn data_up_to_end() {
+        let mut buf = holey();
+        buf.write(28, b"ef").unwrap();
+        assert_eq!(buf.next_data(22), Some(28));
+        assert_eq!(buf.next_data(29), Some(29));
+        // The end of the file counts as a hole.
+        assert_eq!(buf.next_hole(28), Some(30));
+        assert_eq!(buf.next_hole(30), None);
+    }
+}
@@ -6,6 +6,7 @@ import {
   WorkerPool,
 } from "./worker";
 import { isNode, isPlainObject, isURL, loadNodeModule } from "./utils";
+import { Dirent, FdUsage, Found } from "./wasiFS";
 import wasmBinary from "../dist/fs.wasm";
 
 export type MountSource = string | Blob | Uint8Array | { [path: string]: any };
@@ -18,10 +19,53 @@ declare type ReadFileToBlobOptions = {
   type?: string;
 };
 
+declare type MkdirOptions = {
+  // Create missing parents too, and don't fail if the dir exists, like `mkdir -p`.
+  recursive?: boolean;
+};
+
+declare type ReaddirOptions = {
+  // Return dirents rather than names.
+  withFileTypes?: boolean;
+  // List what's in subdirs too, by paths relative to `path`, like Node's `readdir`.
+  recursive?: boolean;
+};
+
 declare type RmdirOptions = {
   recursive?: boolean;
 };
 
+declare type RenameOptions = {
+  // Fail with EEXIST rather than replace `newPath`.
+  noReplace?: boolean;
+  // Swap `oldPath` and `newPath`, which both have to exist.
+  exchange?: boolean;
+};
+
+declare type CopyOptions = {
+  // What to do with files already at `dest` (dirs are merged): fail with EEXIST (the
+  // default), leave them be, or replace them.
+  overwrite?: "error" | "skip" | "replace";
+  // Keep modes and times, like `cp -p`.
+  preserve?: boolean;
+  // Copy what symlinks point to rather than the links, like `cp -L`.
+  follow?: boolean;
+};
+
+declare type FindOptions = {
+  // Globs for paths relative to the dir searched, e.g. `**/*.png`, see `WasiFS.findSync`.
+  include?: string[];
+  exclude?: string[];
+  type?: "file" | "dir" | "symlink" | "chardev" | "fifo";
+  minSize?: number;
+  maxSize?: number;
+  minDepth?: number;
+  maxDepth?: number;
+  // Seconds or dates, both exclusive.
+  modifiedAfter?: number | Date;
+  modifiedBefore?: number | Date;
+};
+
 export class FileSystem {
   workerPool: WorkerPool;
   mod: WebAssembly.Module;
@@ -109,28 +153,128 @@ export class FileSystem {
     return blob.arrayBuffer();
   }
 
-  async mkdir(path: string) {
+  async mkdir(path: string, opts: MkdirOptions = {}) {
     await unwrap<void>(
       this.workerPool.request<FSResponse>({
         type: MessageType.FSRequest,
         fsType: FSRequestType.Mkdir,
+        args: [path, opts.recursive],
+      }),
+      { ["path"]: path }
+    );
+  }
+
+  async mkfifo(path: string) {
+    await unwrap<void>(
+      this.workerPool.request<FSResponse>({
+        type: MessageType.FSRequest,
+        fsType: FSRequestType.Mkfifo,
         args: [path],
       }),
       { ["path"]: path }
     );
   }
 
-  async readdir(path: string): Promise<string[]> {
-    return unwrap<string[]>(
+  // Opening more files than this fails with EMFILE.
+  async setMaxOpenFiles(max: number) {
+    await unwrap<void>(
+      this.workerPool.request<FSResponse>({
+        type: MessageType.FSRequest,
+        fsType: FSRequestType.SetMaxOpenFiles,
+        args: [max],
+      })
+    );
+  }
+
+  async fdUsage(): Promise<FdUsage> {
+    return unwrap<FdUsage>(
+      this.workerPool.request<FSResponse>({
+        type: MessageType.FSRequest,
+        fsType: FSRequestType.FdUsage,
+        args: [],
+      })
+    );
+  }
+
+  // Feeds the stdin of process `pid`. Resolves once all of it is written, unless the
+  // process closes its stdin meanwhile.
+  async stdinWrite(pid: number, buf: Uint8Array): Promise<number> {
+    return unwrap<number>(
+      this.workerPool.requestStdio<FSResponse>({
+        type: MessageType.FSRequest,
+        fsType: FSRequestType.StdinWrite,
+        args: [pid, buf],
+      })
+    );
+  }
+
+  async stdinClose(pid: number) {
+    await unwrap<void>(
+      this.workerPool.requestStdio<FSResponse>({
+        type: MessageType.FSRequest,
+        fsType: FSRequestType.StdinClose,
+        args: [pid],
+      })
+    );
+  }
+
+  // Drains the stdout (1) or stderr (2) of process `pid`. Resolves once there's something
+  // to read, or with null once the process has closed it.
+  async stdioRead(pid: number, fd: number): Promise<Uint8Array | null> {
+    return unwrap<Uint8Array | null>(
+      this.workerPool.requestStdio<FSResponse>({
+        type: MessageType.FSRequest,
+        fsType: FSRequestType.StdioRead,
+        args: [pid, fd],
+      })
+    );
+  }
+
+  async destroyProcess(pid: number) {
+    await unwrap<void>(
+      this.workerPool.requestStdio<FSResponse>({
+        type: MessageType.FSRequest,
+        fsType: FSRequestType.DestroyProcess,
+        args: [pid],
+      })
+    );
+  }
+
+  async readdir(
+    path: string,
+    opts: ReaddirOptions = {}
+  ): Promise<(string | Dirent)[]> {
+    return unwrap<(string | Dirent)[]>(
       this.workerPool.request<FSResponse>({
         type: MessageType.FSRequest,
         fsType: FSRequestType.Readdir,
-        args: [path],
+        args: [path, opts.withFileTypes, opts.recursive],
       }),
       { ["path"]: path }
     );
   }
 
+  async chmod(path: string, mode: number | string) {
+    await unwrap<void>(
+      this.workerPool.request<FSResponse>({
+        type: MessageType.FSRequest,
+        fsType: FSRequestType.Chmod,
+        args: [path, mode],
+      }),
+      { ["path"]: path }
+    );
+  }
+
+  async umask(mask: number): Promise<number> {
+    return unwrap<number>(
+      this.workerPool.request<FSResponse>({
+        type: MessageType.FSRequest,
+        fsType: FSRequestType.Umask,
+        args: [mask],
+      })
+    );
+  }
+
   async rmdir(path: string, opts: RmdirOptions = {}) {
     await unwrap<void>(
       this.workerPool.request<FSResponse>({
@@ -141,6 +285,56 @@ export class FileSystem {
       { ["path"]: path }
     );
   }
+
+  // Replaces whatever is at `newPath`, see `RenameOptions`.
+  async rename(oldPath: string, newPath: string, opts: RenameOptions = {}) {
+    await unwrap<void>(
+      this.workerPool.request<FSResponse>({
+        type: MessageType.FSRequest,
+        fsType: FSRequestType.Rename,
+        args: [oldPath, newPath, opts.noReplace, opts.exchange],
+      }),
+      { ["path"]: oldPath, ["dest"]: newPath }
+    );
+  }
+
+  // Copies a file or dir tree within the file system, like `cp -R`. Mounted URLs are
+  // copied as URLs, without downloading them. Symlinks in the way aren't followed, so a
+  // dir copied onto one fails (with EEXIST, or ENOTDIR when replacing).
+  async copy(src: string, dest: string, opts: CopyOptions = {}) {
+    await unwrap<void>(
+      this.workerPool.request<FSResponse>({
+        type: MessageType.FSRequest,
+        fsType: FSRequestType.Copy,
+        args: [src, dest, opts.overwrite, opts.preserve, opts.follow],
+      }),
+      { ["path"]: src, ["dest"]: dest }
+    );
+  }
+
+  // Finds what matches `opts` under `path` with a single request, returning paths
+  // relative to it with their stats.
+  async find(path: string, opts: FindOptions = {}): Promise<Found[]> {
+    return unwrap<Found[]>(
+      this.workerPool.request<FSResponse>({
+        type: MessageType.FSRequest,
+        fsType: FSRequestType.Find,
+        args: [
+          path,
+          opts.include,
+          opts.exclude,
+          opts.type,
+          opts.minSize,
+          opts.maxSize,
+          opts.minDepth,
+          opts.maxDepth,
+          opts.modifiedAfter,
+          opts.modifiedBefore,
+        ],
+      }),
+      { ["path"]: path }
+    );
+  }
 }
 
 async function resolveNodePaths(source: MountSource): Promise<MountSource> {
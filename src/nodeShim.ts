@@ -118,7 +118,7 @@ export class NodeShim {
     const that = this;
     this.Worker = function () {
       that.createdWorker = true;
-      return new NodeWorker(that.jsPath!, fsModule, fsMemory);
+      return new NodeWorker(that.jsPath!, fsModule, fsMemory, fs.pid);
     };
     const worker_threads = {
       ["Worker"]: this.Worker,
@@ -268,13 +268,15 @@ class NodeWorker {
   constructor(
     jsPath: string,
     fsModule: WebAssembly.Module,
-    fsMemory: WebAssembly.Memory
+    fsMemory: WebAssembly.Memory,
+    pid: number
   ) {
     this.worker = createWorkerSync(
       JSPAWN_PTHREAD,
       jsPath.replace(/.js$/, ".worker.js")
     );
-    this.worker.postMessage([fsModule, fsMemory]);
+    // Threads share the process of the program that started them.
+    this.worker.postMessage([fsModule, fsMemory, pid]);
   }
 
   ["on"](name: string, listener: any) {
@@ -4,6 +4,7 @@ import {
   FSRequestType,
   FSResponse,
   WorkerPool,
+  PreopenOptions,
 } from "./worker";
 import { FileSystem, MountSource } from "./fileSystem";
 import { isNode, loadNodeModule } from "./utils";
@@ -19,8 +20,16 @@ declare type RunOutput = {
   exitCode: number;
 };
 
+declare type StdinChunk = string | Uint8Array;
+
 declare type RunOptions = {
   env?: { [k: string]: string };
+  preopens?: PreopenOptions[];
+  // Fed to the program as it reads, and then closed.
+  stdin?: StdinChunk | Iterable<StdinChunk> | AsyncIterable<StdinChunk>;
+  // Called with the program's output as it's written.
+  onStdout?: (buf: Uint8Array) => void;
+  onStderr?: (buf: Uint8Array) => void;
 };
 
 export class VirtualEnv {
@@ -101,6 +110,7 @@ export class VirtualEnv {
     };
     const decoder = new TextDecoder();
     let errMsg: string | undefined;
+    let pumped: Promise<void> | undefined;
 
     await this.workerPool.subscribe(
       (topic: number) => ({
@@ -110,6 +120,7 @@ export class VirtualEnv {
         args,
         env: opts.env || {},
         wasmPath: this.binarySearchPath,
+        preopens: opts.preopens,
       }),
       (msg: Message) => {
         switch (msg.type) {
@@ -127,9 +138,13 @@ export class VirtualEnv {
           case MessageType.SubprocessRunError:
             errMsg = msg.message;
             break;
+          case MessageType.SubprocessRunStarted:
+            pumped = this.pumpStdio(msg.pid, opts, output);
+            break;
         }
       }
     );
+    await pumped;
 
     if (errMsg) {
       throw new Error(errMsg);
@@ -143,4 +158,63 @@ export class VirtualEnv {
 
     return output;
   }
+
+  // Feeds the stdin of process `pid` and drains its stdout and stderr until it has
+  // exited, and then destroys it. The pipes are bounded, so the program waits for this
+  // to catch up rather than piling its output up in memory. Each of them is pumped on
+  // its own, since the program can block on any one of them.
+  async pumpStdio(pid: number, opts: RunOptions, output: RunOutput) {
+    try {
+      await Promise.all([
+        this.feedStdin(pid, opts.stdin),
+        this.drainStdio(pid, 1, output, opts.onStdout),
+        this.drainStdio(pid, 2, output, opts.onStderr),
+      ]);
+    } finally {
+      await this.fs.destroyProcess(pid);
+    }
+  }
+
+  async drainStdio(
+    pid: number,
+    fd: number,
+    output: RunOutput,
+    callback?: (buf: Uint8Array) => void
+  ) {
+    const decoder = new TextDecoder();
+    for (;;) {
+      const buf = await this.fs.stdioRead(pid, fd);
+      const text = buf
+        ? decoder.decode(buf, { stream: true })
+        : decoder.decode();
+      if (fd === 1) {
+        output.stdout += text;
+      } else {
+        output.stderr += text;
+      }
+      if (!buf) break;
+      if (callback) callback(buf);
+    }
+  }
+
+  async feedStdin(pid: number, stdin: RunOptions["stdin"]) {
+    const encoder = new TextEncoder();
+    const chunks =
+      stdin == null
+        ? []
+        : typeof stdin === "string" || stdin instanceof Uint8Array
+        ? [stdin]
+        : stdin;
+    try {
+      for await (const chunk of chunks) {
+        const buf = typeof chunk === "string" ? encoder.encode(chunk) : chunk;
+        if (buf.byteLength) await this.fs.stdinWrite(pid, buf);
+      }
+      await this.fs.stdinClose(pid);
+    } catch (err) {
+      // The program exited without reading all of it, like with a closed pipe.
+      if ((err as any).code !== "EPIPE") throw err;
+    }
+  }
 }
+
@@ -72,23 +72,19 @@ export const snapshotPreview1 = {
     ctx.fs.fstatSync(fd);
   },
   ["fd_fdstat_get"]: function (ctx: Context, fd: number, fdstatPtr: number) {
-    const filestat = ctx.fs.fstatSync(fd) as t.Filestat;
-    const fdstat = new t.Fdstat([filestat.filetype, 0, BigInt(0), BigInt(0)]);
+    const fdstat = ctx.fs.fdstatSync(fd);
     t.fdstat_t.set(ctx.mem, fdstatPtr, fdstat);
   },
-  ["fd_fdstat_set_flags"]: function (
-    _ctx: Context,
-    _fd: number,
-    _flags: number
-  ) {
-    throw e.ERRNO_NOSYS;
+  ["fd_fdstat_set_flags"]: function (ctx: Context, fd: number, flags: number) {
+    ctx.fs.fdstatSetFlagsSync(fd, flags);
   },
   ["fd_fdstat_set_rights"]: function (
-    _fd: number,
-    _rightsBase: bigint,
-    _rightsInheriting: bigint
+    ctx: Context,
+    fd: number,
+    rightsBase: bigint,
+    rightsInheriting: bigint
   ) {
-    throw e.ERRNO_NOSYS;
+    ctx.fs.fdstatSetRightsSync(fd, rightsBase, rightsInheriting);
   },
   ["fd_filestat_get"]: function (
     ctx: Context,
@@ -102,11 +98,14 @@ export const snapshotPreview1 = {
     ctx.fs.ftruncateSync(fd, size);
   },
   ["fd_filestat_set_times"]: function (
-    _fd: number,
-    _atim: bigint,
-    _mtim: bigint,
-    _flags: number
-  ) {},
+    ctx: Context,
+    fd: number,
+    atim: bigint,
+    mtim: bigint,
+    fstflags: number
+  ) {
+    ctx.fs.fsetTimesSync(fd, atim, mtim, fstflags);
+  },
   ["fd_read"]: function (
     ctx: Context,
     fd: number,
@@ -224,8 +223,8 @@ export const snapshotPreview1 = {
     let bufUsed = 0;
     for (const ent of ents) {
       const dirent = new t.Dirent([
-        ent.cookie + BigInt(1),
-        BigInt(0),
+        BigInt(ent.cookie) + BigInt(1),
+        BigInt(ent.ino),
         ent.name.length,
         ent.type,
       ]);
@@ -260,111 +259,150 @@ export const snapshotPreview1 = {
   },
   ["path_create_directory"]: function (
     ctx: Context,
-    _dirFd: number,
+    dirFd: number,
     pathPtr: number,
     pathLen: number
   ) {
     const path = ctx.readPath(pathPtr, pathLen);
-    ctx.fs.mkdirSync(path);
+    ctx.fs.mkdiratSync(dirFd, path);
   },
   ["path_filestat_get"]: function (
     ctx: Context,
-    _dirFd: number,
-    _flags: number,
+    dirFd: number,
+    flags: number,
     pathPtr: number,
     pathLen: number,
     filestatPtr: number
   ) {
     const path = ctx.readPath(pathPtr, pathLen);
-    const filestat = ctx.fs.lstatSync(path);
+    const filestat = ctx.fs.statatSync(
+      dirFd,
+      path,
+      (flags & c.LOOKUPFLAGS_SYMLINK_FOLLOW) !== 0
+    );
     t.filestat_t.set(ctx.mem, filestatPtr, filestat);
   },
   ["path_filestat_set_times"]: function (
-    _ctx: Context,
-    _dirfd: number,
-    _flags: number,
-    _path: number,
-    _atim: bigint,
-    _mtim: bigint,
-    _fstflags: number
-  ) {},
+    ctx: Context,
+    dirFd: number,
+    flags: number,
+    pathPtr: number,
+    pathLen: number,
+    atim: bigint,
+    mtim: bigint,
+    fstflags: number
+  ) {
+    const path = ctx.readPath(pathPtr, pathLen);
+    ctx.fs.setTimesSync(
+      dirFd,
+      path,
+      atim,
+      mtim,
+      fstflags,
+      (flags & c.LOOKUPFLAGS_SYMLINK_FOLLOW) !== 0
+    );
+  },
   ["path_link"]: function (
-    _ctx: Context,
-    _oldFd: number,
-    _oldFlags: number,
-    _oldPathPtr: number,
-    _oldPathLen: number,
-    _newFd: number,
-    _newPathPtr: number,
-    _newPathLen: number
+    ctx: Context,
+    oldFd: number,
+    oldFlags: number,
+    oldPathPtr: number,
+    oldPathLen: number,
+    newFd: number,
+    newPathPtr: number,
+    newPathLen: number
   ) {
-    throw e.ERRNO_NOSYS;
+    const oldPath = ctx.readPath(oldPathPtr, oldPathLen);
+    const newPath = ctx.readPath(newPathPtr, newPathLen);
+    ctx.fs.linkatSync(
+      oldFd,
+      oldPath,
+      newFd,
+      newPath,
+      (oldFlags & c.LOOKUPFLAGS_SYMLINK_FOLLOW) !== 0
+    );
   },
   ["path_open"]: function (
     ctx: Context,
-    _dirFd: number,
+    dirFd: number,
     _dirFlags: number,
     pathPtr: number,
     pathLen: number,
     oflags: number,
-    _fsRightsBaseRaw: bigint,
-    _fsRightsInheritingRaw: bigint,
+    fsRightsBaseRaw: bigint,
+    fsRightsInheritingRaw: bigint,
     fdflags: number,
     fdPtr: number
   ) {
     const path = ctx.readPath(pathPtr, pathLen);
-    const fd = ctx.fs.openSync(path, oflags, fdflags);
+    const fd = ctx.fs.openatSync(
+      dirFd,
+      path,
+      oflags,
+      fdflags,
+      fsRightsBaseRaw,
+      fsRightsInheritingRaw
+    );
     t.fd_t.set(ctx.mem, fdPtr, fd);
   },
   ["path_readlink"]: function (
-    _ctx: Context,
-    _dirFd: number,
-    _pathPtr: number,
-    _pathLen: number,
-    _bufPtr: number,
-    _bufLen: number,
-    _bufUsedPtr: number
+    ctx: Context,
+    dirFd: number,
+    pathPtr: number,
+    pathLen: number,
+    bufPtr: number,
+    bufLen: number,
+    bufUsedPtr: number
   ) {
-    throw e.ERRNO_NOENT;
+    const path = ctx.readPath(pathPtr, pathLen);
+    const target = t.string_t.enc.encode(ctx.fs.readlinkatSync(dirFd, path));
+    // Like POSIX `readlink`, the target gets truncated to fit the buffer.
+    const bufUsed = Math.min(target.length, bufLen);
+    ctx.mem.u8.set(target.subarray(0, bufUsed), bufPtr);
+    t.size_t.set(ctx.mem, bufUsedPtr, bufUsed);
   },
   ["path_remove_directory"]: function (
     ctx: Context,
-    _dirFd: number,
+    dirFd: number,
     pathPtr: number,
     pathLen: number
   ) {
     const path = ctx.readPath(pathPtr, pathLen);
-    ctx.fs.rmdirSync(path);
+    ctx.fs.rmdiratSync(dirFd, path);
   },
   ["path_rename"]: function (
     ctx: Context,
-    _srcDirFd: number,
+    srcDirFd: number,
     srcPathPtr: number,
     srcPathLen: number,
-    _dstDirFd: number,
+    dstDirFd: number,
     dstPathPtr: number,
     dstPathLen: number
   ) {
     const srcPath = ctx.readPath(srcPathPtr, srcPathLen);
     const dstPath = ctx.readPath(dstPathPtr, dstPathLen);
-    ctx.fs.renameSync(srcPath, dstPath);
+    ctx.fs.renameatSync(srcDirFd, srcPath, dstDirFd, dstPath);
   },
   ["path_symlink"]: function (
-    _ctx: Context,
-    _oldPath: number,
-    _fd: number,
-    _newPath: number
+    ctx: Context,
+    oldPathPtr: number,
+    oldPathLen: number,
+    fd: number,
+    newPathPtr: number,
+    newPathLen: number
   ) {
-    throw e.ERRNO_NOSYS;
+    const target = ctx.readPath(oldPathPtr, oldPathLen);
+    const path = ctx.readPath(newPathPtr, newPathLen);
+    ctx.fs.symlinkatSync(target, fd, path);
   },
   ["path_unlink_file"]: function (
     ctx: Context,
-    _dirFd: number,
+    dirFd: number,
     pathPtr: number,
     pathLen: number
   ) {
     const path = ctx.readPath(pathPtr, pathLen);
-    ctx.fs.unlinkSync(path);
+    ctx.fs.unlinkatSync(dirFd, path);
   },
   ["poll_oneoff"]: function (
     _ctx: Context,
@@ -418,6 +456,34 @@ export const snapshotPreview1 = {
   },
 };
 
+// Calls WASI has no equivalent for, which guests import from the "jspawn" module. They
+// return an errno like the WASI ones, e.g. for an `fcntl` shim to lock files with.
+export const jspawnCalls = {
+  // See `WasiFS.lockSync`. With `nonblock` set, this fails with EAGAIN rather than waiting.
+  ["fd_lock"]: function (
+    ctx: Context,
+    fd: number,
+    exclusive: number,
+    start: bigint,
+    len: bigint,
+    nonblock: number
+  ) {
+    if (!nonblock) {
+      ctx.fs.lockSync(fd, !!exclusive, start, len);
+    } else if (!ctx.fs.tryLockSync(fd, !!exclusive, start, len)) {
+      throw e.ERRNO_AGAIN;
+    }
+  },
+  ["fd_unlock"]: function (
+    ctx: Context,
+    fd: number,
+    start: bigint,
+    len: bigint
+  ) {
+    ctx.fs.unlockSync(fd, start, len);
+  },
+};
+
 class IOVecs {
   bufs: Uint8Array[];
 
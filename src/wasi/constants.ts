@@ -1,9 +1,16 @@
 export const CLOCKID_REALTIME = 0;
 
+export const FILETYPE_UNKNOWN = 0;
+export const FILETYPE_CHARACTER_DEVICE = 2;
 export const FILETYPE_DIRECTORY = 3;
 export const FILETYPE_REGULAR_FILE = 4;
 export const FILETYPE_SYMBOLIC_LINK = 7;
 
+export const FDFLAGS_APPEND = 1 << 0;
+export const FDFLAGS_NONBLOCK = 1 << 2;
+
+export const LOOKUPFLAGS_SYMLINK_FOLLOW = 1 << 0;
+
 export const WHENCE_SET = 0;
 export const WHENCE_CUR = 1;
 export const WHENCE_END = 2;
@@ -12,3 +19,48 @@ export const OFLAGS_CREAT = 1 << 0;
 export const OFLAGS_DIRECTORY = 1 << 1;
 export const OFLAGS_EXCL = 1 << 2;
 export const OFLAGS_TRUNC = 1 << 3;
+
+export const RIGHTS_FD_READ = BigInt(1 << 1);
+export const RIGHTS_FD_WRITE = BigInt(1 << 6);
+export const RIGHTS_FD_ALLOCATE = BigInt(1 << 8);
+export const RIGHTS_PATH_CREATE_DIRECTORY = BigInt(1 << 9);
+export const RIGHTS_PATH_CREATE_FILE = BigInt(1 << 10);
+export const RIGHTS_PATH_LINK_SOURCE = BigInt(1 << 11);
+export const RIGHTS_PATH_LINK_TARGET = BigInt(1 << 12);
+export const RIGHTS_FD_READDIR = BigInt(1 << 14);
+export const RIGHTS_PATH_RENAME_SOURCE = BigInt(1 << 16);
+export const RIGHTS_PATH_RENAME_TARGET = BigInt(1 << 17);
+export const RIGHTS_PATH_FILESTAT_SET_SIZE = BigInt(1 << 19);
+export const RIGHTS_PATH_FILESTAT_SET_TIMES = BigInt(1 << 20);
+export const RIGHTS_FD_FILESTAT_SET_SIZE = BigInt(1 << 22);
+export const RIGHTS_FD_FILESTAT_SET_TIMES = BigInt(1 << 23);
+export const RIGHTS_PATH_SYMLINK = BigInt(1 << 24);
+export const RIGHTS_PATH_REMOVE_DIRECTORY = BigInt(1 << 25);
+export const RIGHTS_PATH_UNLINK_FILE = BigInt(1 << 26);
+// All 29 rights.
+export const RIGHTS_ALL = BigInt((1 << 29) - 1);
+// Everything but the rights to create, change or remove files.
+export const RIGHTS_READONLY =
+  RIGHTS_ALL &
+  ~(
+    RIGHTS_FD_WRITE |
+    RIGHTS_FD_ALLOCATE |
+    RIGHTS_PATH_CREATE_DIRECTORY |
+    RIGHTS_PATH_CREATE_FILE |
+    RIGHTS_PATH_LINK_SOURCE |
+    RIGHTS_PATH_LINK_TARGET |
+    RIGHTS_PATH_RENAME_SOURCE |
+    RIGHTS_PATH_RENAME_TARGET |
+    RIGHTS_PATH_FILESTAT_SET_SIZE |
+    RIGHTS_PATH_FILESTAT_SET_TIMES |
+    RIGHTS_FD_FILESTAT_SET_SIZE |
+    RIGHTS_FD_FILESTAT_SET_TIMES |
+    RIGHTS_PATH_SYMLINK |
+    RIGHTS_PATH_REMOVE_DIRECTORY |
+    RIGHTS_PATH_UNLINK_FILE
+  );
+
+export const FSTFLAGS_ATIM = 1 << 0;
+export const FSTFLAGS_ATIM_NOW = 1 << 1;
+export const FSTFLAGS_MTIM = 1 << 2;
+export const FSTFLAGS_MTIM_NOW = 1 << 3;
@@ -1,23 +1,43 @@
+export const ERRNO_ACCES = 2;
+export const ERRNO_AGAIN = 6;
 export const ERRNO_BADF = 8;
 export const ERRNO_EXIST = 20;
+export const ERRNO_FBIG = 22;
 export const ERRNO_INVAL = 28;
 export const ERRNO_ISDIR = 31;
+export const ERRNO_LOOP = 32;
+export const ERRNO_MFILE = 33;
 export const ERRNO_NOENT = 44;
 export const ERRNO_NOSYS = 52;
 export const ERRNO_NOTDIR = 54;
 export const ERRNO_NOTEMPTY = 55;
+export const ERRNO_NXIO = 60;
+export const ERRNO_PERM = 63;
+export const ERRNO_PIPE = 64;
+export const ERRNO_SPIPE = 70;
+export const ERRNO_SRCH = 71;
 export const ERRNO_NOTCAPABLE = 76;
 
 export function errnoName(errno: number): string {
   switch (errno) {
+    case ERRNO_ACCES:
+      return "ACCES";
+    case ERRNO_AGAIN:
+      return "AGAIN";
     case ERRNO_BADF:
       return "BADF";
     case ERRNO_EXIST:
       return "EXIST";
+    case ERRNO_FBIG:
+      return "FBIG";
     case ERRNO_INVAL:
       return "INVAL";
     case ERRNO_ISDIR:
       return "ISDIR";
+    case ERRNO_LOOP:
+      return "LOOP";
+    case ERRNO_MFILE:
+      return "MFILE";
     case ERRNO_NOENT:
       return "NOENT";
     case ERRNO_NOSYS:
@@ -26,6 +46,16 @@ export function errnoName(errno: number): string {
       return "NOTDIR";
     case ERRNO_NOTEMPTY:
       return "NOTEMPTY";
+    case ERRNO_NXIO:
+      return "NXIO";
+    case ERRNO_PERM:
+      return "PERM";
+    case ERRNO_PIPE:
+      return "PIPE";
+    case ERRNO_SPIPE:
+      return "SPIPE";
+    case ERRNO_SRCH:
+      return "SRCH";
     case ERRNO_NOTCAPABLE:
       return "NOTCAPABLE";
     default:
@@ -25,21 +25,97 @@ const enum FSRequest {
   LstatSync,
   Mount,
   Chdir,
-  CWD,
+  Cwd,
+  SymlinkSync,
+  ReadlinkSync,
+  StatSync,
+  LinkSync,
+  UtimesSync,
+  FutimesSync,
+  ChmodSync,
+  AccessSync,
+  Umask,
+  MkfifoSync,
+  FdstatSync,
+  FdstatSetRightsSync,
+  FdstatSetFlagsSync,
+  SetMaxOpenFiles,
+  FdUsage,
+  DupSync,
+  Dup2Sync,
+  CreateProcess,
+  DestroyProcess,
+  PreopenSync,
+  ExitProcess,
+  StdinWriteSync,
+  StdinCloseSync,
+  StdioReadSync,
+  LockSync,
+  UnlockSync,
+  CopySync,
+  FindSync,
 }
 
-declare type Dirent = {
+export type Dirent = {
   name: string;
   type: number;
-  cookie: bigint;
+  cookie: number;
+  ino: number;
 };
 
 declare type ReaddirOptions = {
   withFileTypes?: boolean;
+  // List what's in subdirs too, by paths relative to `path` (dirents get them as their
+  // `name`), like Node's `readdir`.
+  recursive?: boolean;
+};
+declare type MkdirOptions = {
+  // Create missing parents too, and don't fail if the dir exists, like `mkdir -p`.
+  recursive?: boolean;
 };
 declare type RmdirOptions = {
   recursive?: boolean;
 };
+declare type CopyOptions = {
+  // What to do with files already at the destination (dirs are merged): fail with
+  // EEXIST (the default), leave them be, or replace them.
+  overwrite?: "error" | "skip" | "replace";
+  // Keep modes and times, like `cp -p`.
+  preserve?: boolean;
+  // Copy what symlinks point to rather than the links, like `cp -L`.
+  follow?: boolean;
+};
+declare type FindOptions = {
+  // Globs for paths relative to the dir searched, e.g. `**/*.png`: `*` and `?` don't
+  // match `/`, a `**` component matches any number of dirs, and there are `[a-z]` and
+  // `[!a-z]` classes. Paths have to match one of `include` (if given and not empty) and
+  // none of `exclude`, and nothing in an excluded dir is found.
+  include?: string[];
+  exclude?: string[];
+  type?: "file" | "dir" | "symlink" | "chardev" | "fifo";
+  minSize?: number;
+  maxSize?: number;
+  // The entries of the dir searched are at depth 1.
+  minDepth?: number;
+  maxDepth?: number;
+  // Seconds or dates, like `utimesSync`. Both are exclusive.
+  modifiedAfter?: number | Date;
+  modifiedBefore?: number | Date;
+};
+export type Found = {
+  path: string;
+  stats: wasi.Filestat | any;
+};
+
+export type FdUsage = {
+  fds: number[];
+  max: number;
+};
+
+export type Preopen = {
+  fd: number;
+  name: string;
+};
 
 export type FromWorkerMessage = {
   id: number;
@@ -71,6 +147,7 @@ export const constants = {
   ["O_APPEND"]: 1024,
   ["O_CREAT"]: 64,
   ["O_EXCL"]: 128,
+  ["O_NONBLOCK"]: 2048,
   ["O_NOCTTY"]: 256,
   ["O_RDONLY"]: 0,
   ["O_RDWR"]: 2,
@@ -81,16 +158,27 @@ export const constants = {
   ["S_IFREG"]: 32768,
   ["S_IFDIR"]: 16384,
   ["S_IFLNK"]: 40960,
+  ["S_IFCHR"]: 8192,
+  ["S_IFIFO"]: 4096,
+  ["F_OK"]: 0,
+  ["R_OK"]: 4,
+  ["W_OK"]: 2,
+  ["X_OK"]: 1,
+  ["RENAME_NOREPLACE"]: 1,
+  ["RENAME_EXCHANGE"]: 2,
 };
 
 export class WasiFS {
   bindings!: Bindings;
   isNodeAPI: boolean;
   textEncoder: TextEncoder;
+  // Every program gets its own fds and cwd. The host is process 0.
+  pid: number;
 
-  constructor(isNodeAPI: boolean = false) {
+  constructor(isNodeAPI: boolean = false, pid: number = 0) {
     this.isNodeAPI = isNodeAPI;
     this.textEncoder = new TextEncoder();
+    this.pid = pid;
   }
 
   static async instantiate(
@@ -103,15 +191,64 @@ export class WasiFS {
   }
 
   nodeFS(): WasiFS {
-    const fs = new WasiFS(true);
+    const fs = new WasiFS(true, this.pid);
+    fs.bindings = this.bindings;
+    return fs;
+  }
+
+  // Creates a process that starts out in this one's cwd.
+  spawn(): WasiFS {
+    return this.process(this.requestSync(FSRequest.CreateProcess, []));
+  }
+
+  // Closes everything the process still has open. Its stdout and stderr can still be
+  // read until it's destroyed.
+  exit() {
+    this.requestSync(FSRequest.ExitProcess, []);
+  }
+
+  destroy() {
+    this.requestSync(FSRequest.DestroyProcess, []);
+  }
+
+  // The same file system, as seen by another process.
+  process(pid: number): WasiFS {
+    const fs = new WasiFS(this.isNodeAPI, pid);
     fs.bindings = this.bindings;
     return fs;
   }
 
+  // Feeds the process' stdin. Blocks until all of it is written, unless the process
+  // closes its stdin meanwhile.
+  stdinWriteSync(buffer: Uint8Array): number {
+    return this.requestSync(
+      FSRequest.StdinWriteSync,
+      [big(buffer.byteLength)],
+      [buffer]
+    );
+  }
+
+  // The process reads EOF once it has read everything written so far.
+  stdinCloseSync() {
+    this.requestSync(FSRequest.StdinCloseSync, []);
+  }
+
+  // Drains the process' stdout (1) or stderr (2). Blocks until there's something to
+  // read, and returns null once the process has closed it.
+  stdioReadSync(fd: number, length: number = 65536): Uint8Array | null {
+    const buf = new Uint8Array(length);
+    const nread = this.requestSync(
+      FSRequest.StdioReadSync,
+      [fd, big(length)],
+      [buf]
+    );
+    return nread === 0 ? null : buf.subarray(0, nread);
+  }
+
   requestSync(req: FSRequest, args: any[], bufs: Buffer[] = []): any {
     try {
       const uint8Bufs = bufs.map(toUint8);
-      const out = this.bindings!.requestSync(req, args, uint8Bufs);
+      const out = this.bindings!.requestSync(this.pid, req, args, uint8Bufs);
       return this.handleOut(out);
     } catch (err) {
       this.handleErr(err);
@@ -148,7 +285,7 @@ export class WasiFS {
     length = length == null ? buffer.byteLength : length;
     return this.requestSync(
       FSRequest.ReadSync,
-      [fd, big(position)],
+      [fd, big(length), big(position)],
       [toUint8(buffer).subarray(offset, offset + length!)]
     );
   }
@@ -171,6 +308,28 @@ export class WasiFS {
     );
   }
 
+  fdstatSync(fd: number): wasi.Fdstat {
+    const stat = this.requestSync(FSRequest.FdstatSync, [fd]);
+    return new wasi.Fdstat([
+      stat["filetype"],
+      stat["flags"],
+      BigInt(stat["rights"]),
+      BigInt(stat["rights_inheriting"]),
+    ]);
+  }
+
+  fdstatSetRightsSync(fd: number, rights: bigint, rightsInheriting: bigint) {
+    this.requestSync(FSRequest.FdstatSetRightsSync, [
+      fd,
+      rights,
+      rightsInheriting,
+    ]);
+  }
+
+  fdstatSetFlagsSync(fd: number, flags: number) {
+    this.requestSync(FSRequest.FdstatSetFlagsSync, [fd, flags]);
+  }
+
   fstatSync(fd: number): wasi.Filestat | any {
     return createStats(
       this.requestSync(FSRequest.FstatSync, [fd]),
@@ -178,10 +337,24 @@ export class WasiFS {
     );
   }
 
-  openSync(path: string, flags: number, fdflags: number = 0): number {
+  openSync(
+    path: string,
+    flags: number,
+    fdflags: number = 0,
+    rights: bigint = wasi.RIGHTS_ALL,
+    rightsInheriting: bigint = wasi.RIGHTS_ALL
+  ): number {
     let oflags = flags;
     if (this.isNodeAPI) {
       oflags = 0;
+      // The access mode decides whether the fd gets the read and write rights.
+      const accmode = flags & 3;
+      if (accmode === constants["O_WRONLY"]) {
+        rights &= ~(wasi.RIGHTS_FD_READ | wasi.RIGHTS_FD_READDIR);
+      }
+      if (accmode === constants["O_RDONLY"]) {
+        rights &= ~wasi.RIGHTS_FD_WRITE;
+      }
       if ((flags & constants["O_CREAT"]) !== 0) {
         oflags |= wasi.OFLAGS_CREAT;
       }
@@ -194,26 +367,88 @@ export class WasiFS {
       if ((flags & constants["O_TRUNC"]) !== 0) {
         oflags |= wasi.OFLAGS_TRUNC;
       }
+      if ((flags & constants["O_APPEND"]) !== 0) {
+        fdflags |= wasi.FDFLAGS_APPEND;
+      }
+      if ((flags & constants["O_NONBLOCK"]) !== 0) {
+        fdflags |= wasi.FDFLAGS_NONBLOCK;
+      }
     }
-    return this.requestSync(FSRequest.OpenSync, [path, oflags, fdflags]);
+    return this.openatSync(
+      undefined,
+      path,
+      oflags,
+      fdflags,
+      rights,
+      rightsInheriting
+    );
+  }
+
+  // The `*at` methods resolve `path` relative to the dir open at `dirFd`, or the cwd
+  // when it's undefined.
+  openatSync(
+    dirFd: number | undefined,
+    path: string,
+    oflags: number,
+    fdflags: number,
+    rights: bigint,
+    rightsInheriting: bigint
+  ): number {
+    return this.requestSync(FSRequest.OpenSync, [
+      dirFd,
+      path,
+      oflags,
+      fdflags,
+      rights,
+      rightsInheriting,
+    ]);
   }
 
   closeSync(fd: number) {
     this.requestSync(FSRequest.CloseSync, [fd]);
   }
 
-  readlinkSync(_path: string): string {
-    this.throwErrno(wasi.ERRNO_NOENT);
+  readlinkSync(path: string): string {
+    return this.readlinkatSync(undefined, path);
+  }
+
+  readlinkatSync(dirFd: number | undefined, path: string): string {
+    return this.requestSync(FSRequest.ReadlinkSync, [dirFd, path]);
+  }
+
+  symlinkSync(target: string, path: string) {
+    this.symlinkatSync(target, undefined, path);
+  }
+
+  symlinkatSync(target: string, dirFd: number | undefined, path: string) {
+    this.requestSync(FSRequest.SymlinkSync, [target, dirFd, path]);
   }
 
-  symlinkSync(_target: string, _path: string) {
-    this.throwErrno(wasi.ERRNO_NOSYS);
+  linkSync(existingPath: string, newPath: string, follow: boolean = false) {
+    this.linkatSync(undefined, existingPath, undefined, newPath, follow);
+  }
+
+  linkatSync(
+    oldDirFd: number | undefined,
+    oldPath: string,
+    newDirFd: number | undefined,
+    newPath: string,
+    follow: boolean
+  ) {
+    this.requestSync(FSRequest.LinkSync, [
+      oldDirFd,
+      oldPath,
+      newDirFd,
+      newPath,
+      follow,
+    ]);
   }
 
   readdirSync(path: string, opts: ReaddirOptions = {}): (string | Dirent)[] {
     return this.requestSync(FSRequest.ReaddirSync, [
       path,
       opts.withFileTypes || false,
+      opts.recursive || false,
     ]);
   }
 
@@ -222,15 +457,90 @@ export class WasiFS {
   }
 
   rmdirSync(path: string, opts: RmdirOptions = {}) {
-    this.requestSync(FSRequest.RmdirSync, [path, opts.recursive || false]);
+    this.rmdiratSync(undefined, path, opts.recursive || false);
+  }
+
+  rmdiratSync(dirFd: number | undefined, path: string, recursive = false) {
+    this.requestSync(FSRequest.RmdirSync, [dirFd, path, recursive]);
   }
 
   unlinkSync(path: string) {
-    this.requestSync(FSRequest.UnlinkSync, [path]);
+    this.unlinkatSync(undefined, path);
+  }
+
+  unlinkatSync(dirFd: number | undefined, path: string) {
+    this.requestSync(FSRequest.UnlinkSync, [dirFd, path]);
+  }
+
+  // `flags` are `RENAME_NOREPLACE` or `RENAME_EXCHANGE`, like Linux's `renameat2`.
+  renameSync(oldPath: string, newPath: string, flags = 0) {
+    this.renameatSync(undefined, oldPath, undefined, newPath, flags);
+  }
+
+  renameatSync(
+    oldDirFd: number | undefined,
+    oldPath: string,
+    newDirFd: number | undefined,
+    newPath: string,
+    flags = 0
+  ) {
+    this.requestSync(FSRequest.RenameSync, [
+      oldDirFd,
+      oldPath,
+      newDirFd,
+      newPath,
+      flags,
+    ]);
+  }
+
+  // Copies a file or dir tree, like `cp -R`. Files backed by a URL are copied without
+  // downloading them.
+  copySync(src: string, dest: string, opts: CopyOptions = {}) {
+    const overwrite = ["error", "skip", "replace"].indexOf(
+      opts.overwrite || "error"
+    );
+    this.requestSync(FSRequest.CopySync, [
+      src,
+      dest,
+      overwrite,
+      opts.preserve || false,
+      opts.follow || false,
+    ]);
   }
 
-  renameSync(oldPath: string, newPath: string) {
-    this.requestSync(FSRequest.RenameSync, [oldPath, newPath]);
+  // Searches the dir tree at `path` in one go, see `FindOptions`. Symlinks aren't
+  // followed, and dirs that can't be read are skipped.
+  findSync(path: string, opts: FindOptions = {}): Found[] {
+    const filetypes = {
+      ["file"]: wasi.FILETYPE_REGULAR_FILE,
+      ["dir"]: wasi.FILETYPE_DIRECTORY,
+      ["symlink"]: wasi.FILETYPE_SYMBOLIC_LINK,
+      ["chardev"]: wasi.FILETYPE_CHARACTER_DEVICE,
+      // FIFOs are the only files without a WASI filetype.
+      ["fifo"]: wasi.FILETYPE_UNKNOWN,
+    };
+    // Patterns are separated by NULs, which can't be in paths. No patterns at all is sent
+    // as none, not as one empty pattern.
+    const patterns = (globs?: string[]) =>
+      globs && globs.length ? globs.join("\0") : undefined;
+    const found = this.requestSync(FSRequest.FindSync, [
+      path,
+      patterns(opts.include),
+      patterns(opts.exclude),
+      opts.type && filetypes[opts.type],
+      big(opts.minSize),
+      big(opts.maxSize),
+      opts.minDepth,
+      opts.maxDepth,
+      opts.modifiedAfter === undefined ? undefined : toNanos(opts.modifiedAfter),
+      opts.modifiedBefore === undefined
+        ? undefined
+        : toNanos(opts.modifiedBefore),
+    ]);
+    return found.map((found: any) => ({
+      ["path"]: found["path"],
+      ["stats"]: createStats(found["stats"], this.isNodeAPI),
+    }));
   }
 
   writeFileSync(path: string, data: Buffer | string | Blob | URL) {
@@ -260,8 +570,16 @@ export class WasiFS {
     return this.requestSync(FSRequest.ReadFile, [path]);
   }
 
-  mkdirSync(path: string) {
-    this.requestSync(FSRequest.MkdirSync, [path]);
+  mkdirSync(path: string, opts: MkdirOptions = {}) {
+    this.mkdiratSync(undefined, path, opts.recursive || false);
+  }
+
+  mkdiratSync(dirFd: number | undefined, path: string, recursive = false) {
+    this.requestSync(FSRequest.MkdirSync, [dirFd, path, recursive]);
+  }
+
+  mkfifoSync(path: string) {
+    this.requestSync(FSRequest.MkfifoSync, [path]);
   }
 
   truncateSync(path: string, size: number = 0) {
@@ -276,13 +594,85 @@ export class WasiFS {
     this.requestSync(FSRequest.FallocateSync, [fd, offset, size]);
   }
 
-  utimesSync(_path: string, _atime: number | Date, _mtime: number | Date) {}
+  utimesSync(path: string, atime: number | Date, mtime: number | Date) {
+    this.setTimesSync(
+      undefined,
+      path,
+      toNanos(atime),
+      toNanos(mtime),
+      wasi.FSTFLAGS_ATIM | wasi.FSTFLAGS_MTIM,
+      true
+    );
+  }
+
+  setTimesSync(
+    dirFd: number | undefined,
+    path: string,
+    atim: bigint,
+    mtim: bigint,
+    fstflags: number,
+    follow: boolean
+  ) {
+    this.requestSync(FSRequest.UtimesSync, [
+      dirFd,
+      path,
+      atim,
+      mtim,
+      fstflags,
+      follow,
+    ]);
+  }
+
+  fsetTimesSync(fd: number, atim: bigint, mtim: bigint, fstflags: number) {
+    this.requestSync(FSRequest.FutimesSync, [fd, atim, mtim, fstflags]);
+  }
+
+  chmodSync(path: string, mode: number | string, follow: boolean = true) {
+    if (typeof mode === "string") {
+      mode = parseInt(mode, 8);
+    }
+    this.requestSync(FSRequest.ChmodSync, [path, mode, follow]);
+  }
+
+  lchmodSync(path: string, mode: number | string) {
+    this.chmodSync(path, mode, false);
+  }
+
+  accessSync(path: string, mode: number = constants["F_OK"]) {
+    this.requestSync(FSRequest.AccessSync, [path, mode]);
+  }
+
+  // Returns the previous mask.
+  umask(mask: number): number {
+    return this.requestSync(FSRequest.Umask, [mask]);
+  }
 
-  chmodSync(_path: string, _mode: number | string) {}
+  setMaxOpenFiles(max: number) {
+    this.requestSync(FSRequest.SetMaxOpenFiles, [max]);
+  }
+
+  fdUsage(): FdUsage {
+    return this.requestSync(FSRequest.FdUsage, []);
+  }
 
   lstatSync(path: string): wasi.Filestat | any {
+    return this.statatSync(undefined, path, false);
+  }
+
+  statSync(path: string): wasi.Filestat | any {
+    return this.statatSync(undefined, path, true);
+  }
+
+  statatSync(
+    dirFd: number | undefined,
+    path: string,
+    follow: boolean
+  ): wasi.Filestat | any {
     return createStats(
-      this.requestSync(FSRequest.LstatSync, [path]),
+      this.requestSync(
+        follow ? FSRequest.StatSync : FSRequest.LstatSync,
+        [dirFd, path]
+      ),
       this.isNodeAPI
     );
   }
@@ -296,10 +686,86 @@ export class WasiFS {
     return this.requestSync(FSRequest.PrestatDirNameSync, [fd]);
   }
 
+  preopens(): Preopen[] {
+    return this.requestSync(FSRequest.PrestatDirNameSync, [undefined]);
+  }
+
+  // Exposes the dir at `path` to the guest as `name`. Paths resolved through it can't
+  // leave `path`, and it only grants `rights`. Going up from it with `..` fails with
+  // ENOTCAPABLE like WASI, unless `clampDotDot` is set, then it stays put like `/..`.
+  preopenSync(
+    path: string,
+    name: string,
+    rights: bigint = wasi.RIGHTS_ALL,
+    clampDotDot: boolean = false
+  ): number {
+    return this.requestSync(FSRequest.PreopenSync, [
+      path,
+      name,
+      rights,
+      clampDotDot,
+    ]);
+  }
+
   renumberSync(from: number, to: number) {
     return this.requestSync(FSRequest.RenumberSync, [from, to]);
   }
 
+  // Takes an advisory lock on `len` bytes from `start` (up to the end of the file when
+  // `len` is 0), waiting for conflicting locks to be released. Locks belong to the open
+  // file, so they're shared with dups and released when the last of them is closed.
+  lockSync(
+    fd: number,
+    exclusive: boolean,
+    start: number | bigint = 0,
+    len: number | bigint = 0
+  ) {
+    this.requestSync(FSRequest.LockSync, [
+      fd,
+      exclusive,
+      big(start),
+      big(len),
+      false,
+    ]);
+  }
+
+  // Like `lockSync`, but returns false rather than waiting.
+  tryLockSync(
+    fd: number,
+    exclusive: boolean,
+    start: number | bigint = 0,
+    len: number | bigint = 0
+  ): boolean {
+    try {
+      this.requestSync(FSRequest.LockSync, [
+        fd,
+        exclusive,
+        big(start),
+        big(len),
+        true,
+      ]);
+      return true;
+    } catch (err) {
+      if (err === wasi.ERRNO_AGAIN || (err as any).code === "EAGAIN") {
+        return false;
+      }
+      throw err;
+    }
+  }
+
+  unlockSync(fd: number, start: number | bigint = 0, len: number | bigint = 0) {
+    this.requestSync(FSRequest.UnlockSync, [fd, big(start), big(len)]);
+  }
+
+  // The new fd shares its position and flags with `fd`.
+  dupSync(fd: number): number {
+    return this.requestSync(FSRequest.DupSync, [fd]);
+  }
+
+  dup2Sync(from: number, to: number): number {
+    return this.requestSync(FSRequest.Dup2Sync, [from, to]);
+  }
+
   seekSync(fd: number, offset: bigint, whence: number): bigint {
     return BigInt(this.requestSync(FSRequest.SeekSync, [fd, offset, whence]));
   }
@@ -319,7 +785,7 @@ export class WasiFS {
   }
 
   cwd(): string {
-    return this.requestSync(FSRequest.CWD, []);
+    return this.requestSync(FSRequest.Cwd, []);
   }
 }
 
@@ -366,16 +832,22 @@ function big(n: bigint | number | undefined): bigint | undefined {
   return n as bigint | undefined;
 }
 
+// Node accepts seconds or dates.
+function toNanos(time: number | Date): bigint {
+  const ms = time instanceof Date ? time.getTime() : time * 1000;
+  return BigInt(Math.round(ms * 1000000));
+}
+
 function createStats(stat: any, isNodeAPI: boolean): wasi.Filestat | any {
   const filestat = new wasi.Filestat([
-    BigInt(0),
-    BigInt(0),
+    BigInt(stat["dev"]),
+    BigInt(stat["ino"]),
     stat["filetype"],
-    BigInt(0),
+    BigInt(stat["nlink"]),
     BigInt(stat["size"]),
-    BigInt(0),
-    BigInt(0),
-    BigInt(0),
+    BigInt(stat["atim"]),
+    BigInt(stat["mtim"]),
+    BigInt(stat["ctim"]),
   ]);
 
   if (!isNodeAPI) return filestat;
@@ -394,7 +866,15 @@ function createStats(stat: any, isNodeAPI: boolean): wasi.Filestat | any {
     case wasi.FILETYPE_SYMBOLIC_LINK:
       mode = constants["S_IFLNK"];
       break;
+    case wasi.FILETYPE_CHARACTER_DEVICE:
+      mode = constants["S_IFCHR"];
+      break;
+    // FIFOs are the only files without a WASI filetype.
+    case wasi.FILETYPE_UNKNOWN:
+      mode = constants["S_IFIFO"];
+      break;
   }
+  mode |= stat["mode"];
   return {
     ["dev"]: Number(filestat.dev),
     ["ino"]: Number(filestat.ino),
@@ -602,6 +1082,22 @@ export class Bindings {
       ["out"](ptr: number, len: number) {
         bindings.out = bindings.readString(ptr, len);
       },
+      ["random"](ptr: number, len: number) {
+        // `getRandomValues` rejects views of shared memory and more than 64KiB at once.
+        const buf = new Uint8Array(len);
+        if (isNode()) {
+          // @ts-ignore
+          requir("crypto")["randomFillSync"](buf);
+        } else {
+          for (let i = 0; i < len; i += 65536) {
+            crypto.getRandomValues(buf.subarray(i, i + 65536));
+          }
+        }
+        bindings.mem.u8.set(buf, ptr);
+      },
+      ["now"](): bigint {
+        return BigInt(Date.now()) * BigInt(1000000);
+      },
       ["println"](ptr: number, len: number) {
         console.log(bindings.readString(ptr, len));
       },
@@ -647,6 +1143,7 @@ export class Bindings {
   }
 
   requestSync(
+    pid: number,
     req: FSRequest,
     args: any[],
     bufs: Uint8Array[]
@@ -654,7 +1151,7 @@ export class Bindings {
     this.buf = bufs[0];
     delete this.out;
     const ptr = args.length ? this.writeArgs(args) : 0;
-    const errno = this.exports["request"](req, ptr, args.length);
+    const errno = this.exports["request"](pid, req, ptr, args.length);
     if (errno) throw errno;
     return this.out ? this.out : this.buf;
   }
@@ -677,7 +1174,12 @@ export class Bindings {
         dv.setUint32(offset + 1, arg, true);
       } else if (typeof arg === "bigint") {
         type = 3;
-        dv.setBigUint64(offset + 1, arg, true);
+        // Signed args (like seek offsets) are read back with `as_i64`.
+        if (arg < 0) {
+          dv.setBigInt64(offset + 1, arg, true);
+        } else {
+          dv.setBigUint64(offset + 1, arg, true);
+        }
       } else if (typeof arg === "boolean") {
         type = 4;
         dv.setUint8(offset + 1, arg ? 1 : 0);
@@ -16,11 +16,19 @@ export class WorkerPool {
   queue: Deferred<WorkerExt>[];
   workers: WorkerState[];
   maxWorkers: number;
+  // Workers outside of the pool, see `requestStdio`.
+  stdioWorkers: WorkerExt[];
+  idleStdioWorkers: WorkerExt[];
+  stdioRequests: number;
+  stdioTimeout?: number;
 
   constructor(maxWorkers: number) {
     this.maxWorkers = maxWorkers;
     this.queue = [];
     this.workers = [];
+    this.stdioWorkers = [];
+    this.idleStdioWorkers = [];
+    this.stdioRequests = 0;
   }
 
   async subscribe(
@@ -33,11 +41,12 @@ export class WorkerPool {
     worker.channel.send(createMsg(topic));
     await worker.channel.sub(topic, handler);
 
-    this.reclaim(worker);
-
+    // Before it's reclaimed, which can hand it to a queued request right away.
     if (isNode()) {
       worker.terminate(this);
     }
+
+    this.reclaim(worker);
   }
 
   async request<T>(msg: Message, transfers?: any[]): Promise<T> {
@@ -45,43 +54,85 @@ export class WorkerPool {
 
     const ret = await worker.channel.req<T>(msg, transfers);
 
-    this.reclaim(worker);
-
+    // Before it's reclaimed, which can hand it to a queued request right away.
     if (isNode()) {
       worker.terminate(this);
     }
 
+    this.reclaim(worker);
+
     return ret;
   }
 
+  // Like `request`, but for the host's end of a program's stdio. These block until the
+  // program catches up, and the programs can take up every worker in the pool while they
+  // wait on the host, so each of these gets a worker of its own outside of the pool.
+  // Those are kept for the next requests once they're done.
+  async requestStdio<T>(msg: Message, transfers?: any[]): Promise<T> {
+    clearTimeout(this.stdioTimeout);
+    this.stdioRequests++;
+    let worker: WorkerExt | undefined;
+    try {
+      worker = this.idleStdioWorkers.pop();
+      if (!worker) {
+        worker = await this.newWorker();
+        this.stdioWorkers.push(worker);
+      }
+      return await worker.channel.req<T>(msg, transfers);
+    } finally {
+      if (worker) this.idleStdioWorkers.push(worker);
+      this.stdioRequests--;
+      // Like the pool's workers, they go once they're idle so they don't keep Node
+      // running.
+      if (isNode() && !this.stdioRequests) {
+        this.stdioTimeout = setTimeout(() => {
+          for (const worker of this.stdioWorkers) {
+            worker.channel.worker.terminate();
+          }
+          this.stdioWorkers = [];
+          this.idleStdioWorkers = [];
+        }) as unknown as number;
+      }
+    }
+  }
+
   terminate() {
     for (const worker of this.workers) {
       worker.worker.terminateSync(this);
     }
+    clearTimeout(this.stdioTimeout);
+    for (const worker of this.stdioWorkers) {
+      worker.channel.worker.terminate();
+    }
+    this.stdioWorkers = [];
+    this.idleStdioWorkers = [];
   }
 
   next(): Promise<WorkerExt> {
     const def = new Deferred() as Deferred<WorkerExt>;
     this.queue.push(def);
-    this.dequeue().catch(def.reject);
+    this.dequeue();
     return def.promise;
   }
 
   async dequeue() {
     if (this.queue.length === 0) return;
-    const def = this.queue.shift()!;
     let worker = this.workers.find((worker: WorkerState) => worker.idle);
+    // Otherwise the request waits for a worker to be reclaimed or removed.
+    if (!worker && this.workers.length === this.maxWorkers) return;
+    const def = this.queue.shift()!;
     if (!worker) {
-      if (this.workers.length === this.maxWorkers) {
-        return;
-      } else {
+      try {
         worker = { worker: await this.newWorker() } as WorkerState;
-        this.workers.push(worker);
+      } catch (err) {
+        def.reject(err);
+        return;
       }
+      this.workers.push(worker);
     }
     worker.idle = false;
     clearTimeout(worker.worker.terminateTimeout);
-    def!.resolve(worker.worker);
+    def.resolve(worker.worker);
   }
 
   async newWorker(): Promise<WorkerExt> {
@@ -100,6 +151,7 @@ export class WorkerPool {
       (item: WorkerState) => item.worker === worker
     );
     state!.idle = true;
+    this.dequeue();
   }
 
   remove(worker: WorkerExt) {
@@ -107,6 +159,7 @@ export class WorkerPool {
       (item: WorkerState) => item.worker === worker
     );
     this.workers.splice(pos, 1);
+    this.dequeue();
   }
 }
 
@@ -235,6 +288,7 @@ export type Message =
   | SubprocessRunStderr
   | SubprocessRunExitCode
   | SubprocessRunError
+  | SubprocessRunStarted
   | FSRequest
   | FSResponse;
 
@@ -248,6 +302,7 @@ export const enum MessageType {
   SubprocessRunError,
   FSRequest,
   FSResponse,
+  SubprocessRunStarted,
 }
 
 export type WorkerInit = {
@@ -267,6 +322,17 @@ export type SubprocessRun = {
   args: string[];
   env: { [k: string]: string };
   wasmPath: string[];
+  preopens?: PreopenOptions[];
+};
+
+// A dir to expose to the program as `name`, instead of its cwd as `/`. Only WASI programs
+// can be given these.
+export type PreopenOptions = {
+  path: string;
+  name: string;
+  readonly?: boolean;
+  // Makes `..` in the dir stay put like `/..` does, see `WasiFS.preopenSync`.
+  clampDotDot?: boolean;
 };
 
 export type SubprocessRunStdout = {
@@ -289,6 +355,12 @@ export type SubprocessRunError = {
   message: string;
 };
 
+// The program's process, whose stdio the host pumps and which it destroys after.
+export type SubprocessRunStarted = {
+  type: MessageType.SubprocessRunStarted;
+  pid: number;
+};
+
 export const enum FSRequestType {
   WriteFile,
   ReadFileToBlob,
@@ -297,6 +369,18 @@ export const enum FSRequestType {
   Rmdir,
   Mount,
   Chdir,
+  Chmod,
+  Umask,
+  Mkfifo,
+  SetMaxOpenFiles,
+  FdUsage,
+  StdinWrite,
+  StdinClose,
+  StdioRead,
+  DestroyProcess,
+  Rename,
+  Copy,
+  Find,
 }
 
 export type FSRequest = {
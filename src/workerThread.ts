@@ -11,7 +11,7 @@ import {
   JSPAWN_PTHREAD,
 } from "./worker";
 import { resizeBuffer, isNode, Deferred, requir, absURL } from "./utils";
-import { WasiFS } from "./wasiFS";
+import { WasiFS, constants } from "./wasiFS";
 import * as wasi from "./wasi/index";
 
 function main() {
@@ -121,95 +121,134 @@ class WorkerThread {
       }
     }
 
-    const stdout = new LineOut((buf: Uint8Array) => {
-      this.channel.pub(msg.topic, {
-        type: MessageType.SubprocessRunStdout,
-        buf,
-      });
+    // Assuming this is Emscripten if no WASI exports are found.
+    const isEmscripten = !WebAssembly.Module.exports(mod).find(
+      (exp) => exp.name === "_start" || exp.name === "_initialize"
+    );
+    if (isEmscripten && msg.preopens) {
+      // Emscripten sees the whole file system through NODEFS, so they couldn't confine it.
+      return this.channel.pub(
+        msg.topic,
+        {
+          type: MessageType.SubprocessRunError,
+          message: `preopens are only supported for WASI programs: ${msg.program}`,
+        },
+        true
+      );
+    }
+
+    let exitCode;
+    // The program gets its own fds and cwd, which are released once it exits. The host
+    // pumps its stdio meanwhile, and destroys it once it's drained.
+    const fs = this.fs.spawn();
+    this.channel.pub(msg.topic, {
+      type: MessageType.SubprocessRunStarted,
+      pid: fs.pid,
     });
-    const stderr = new LineOut((buf: Uint8Array) => {
-      this.channel.pub(msg.topic, {
-        type: MessageType.SubprocessRunStderr,
-        buf,
+    try {
+      if (msg.preopens) {
+        // Only the given dirs are visible to the program.
+        for (const { fd } of fs.preopens()) {
+          fs.closeSync(fd);
+        }
+        for (const { path, name, readonly, clampDotDot } of msg.preopens) {
+          const rights = readonly ? wasi.RIGHTS_READONLY : wasi.RIGHTS_ALL;
+          fs.preopenSync(path, name, rights, !!clampDotDot);
+        }
+      }
+      if (!isEmscripten) {
+        // The devices are only in the root dir, which the program's `/` might not be.
+        fs.preopenSync("~/dev", "/dev");
+      }
+      const stdout = new LineOut((buf: Uint8Array) => {
+        this.channel.pub(msg.topic, {
+          type: MessageType.SubprocessRunStdout,
+          buf,
+        });
+      });
+      const stderr = new LineOut((buf: Uint8Array) => {
+        this.channel.pub(msg.topic, {
+          type: MessageType.SubprocessRunStderr,
+          buf,
+        });
       });
-    });
-    let exitCode;
-
-    if (
-      !WebAssembly.Module.exports(mod).find(
-        (exp) => exp.name === "_start" || exp.name === "_initialize"
-      )
-    ) {
-      // Assuming this is Emscripten if no WASI exports are found.
-      const nodeShim = new NodeShim(
-        this.fs.nodeFS(),
-        this.fsModule,
-        this.fsMemory
-      );
-      const jsPath = binaryPath.replace(/wasm$/, "js");
-      const exports = await nodeShim.eval(jsPath, wasmBuf);
-      const Module = Object.values(exports)[0]!;
 
-      const stdinCallback = () => null;
-      const stdoutCallback = stdout.push.bind(stdout);
-      const stderrCallback = stderr.push.bind(stderr);
+      if (isEmscripten) {
+        const nodeShim = new NodeShim(
+          fs.nodeFS(),
+          this.fsModule,
+          this.fsMemory
+        );
+        const jsPath = binaryPath.replace(/wasm$/, "js");
+        const exports = await nodeShim.eval(jsPath, wasmBuf);
+        const Module = Object.values(exports)[0]!;
 
-      // @ts-ignore
-      const emMod = await Module({
-        ["noInitialRun"]: true,
-        ["noExitRuntime"]: false,
-        ["noFSInit"]: true,
-        ["locateFile"]: () => binaryPath,
-        ["preRun"]: (mod: any) => {
-          Object.assign(mod["ENV"], msg.env);
-        },
-        ["instantiateWasm"]: async (imports: any, cb: any) => {
-          delete nodeShim.wasmBuf;
-          const instance = await WebAssembly.instantiate(mod, imports);
-          cb(instance, mod);
-          return {};
-        },
-      });
+        const stdinCallback = () => null;
+        const stdoutCallback = stdout.push.bind(stdout);
+        const stderrCallback = stderr.push.bind(stderr);
 
-      emMod["FS"]["setIgnorePermissions"](true);
-      emMod["FS"]["init"](stdinCallback, stdoutCallback, stderrCallback);
-      let working = "/working";
-      emMod["FS"]["mkdir"](working);
-      emMod["FS"]["mount"](emMod["NODEFS"], { root: "." }, working);
-      const currentDir = this.fs.cwd();
-      for (const comp of currentDir.split("/")) {
-        if (comp) {
-          working += "/~/~";
+        // @ts-ignore
+        const emMod = await Module({
+          ["noInitialRun"]: true,
+          ["noExitRuntime"]: false,
+          ["noFSInit"]: true,
+          ["locateFile"]: () => binaryPath,
+          ["preRun"]: (mod: any) => {
+            Object.assign(mod["ENV"], msg.env);
+          },
+          ["instantiateWasm"]: async (imports: any, cb: any) => {
+            delete nodeShim.wasmBuf;
+            const instance = await WebAssembly.instantiate(mod, imports);
+            cb(instance, mod);
+            return {};
+          },
+        });
+
+        emMod["FS"]["setIgnorePermissions"](true);
+        emMod["FS"]["init"](stdinCallback, stdoutCallback, stderrCallback);
+        let working = "/working";
+        emMod["FS"]["mkdir"](working);
+        emMod["FS"]["mount"](emMod["NODEFS"], { root: "." }, working);
+        const currentDir = fs.cwd();
+        for (const comp of currentDir.split("/")) {
+          if (comp) {
+            working += "/~/~";
+          }
         }
-      }
-      emMod["FS"]["chdir"](working);
-
-      exitCode = emMod["callMain"](msg.args);
-      if (nodeShim.createdWorker) {
-        const deferred = new Deferred();
-        nodeShim.onExit = (exitCode_: number) => {
-          exitCode = exitCode_;
-          deferred.resolve(0);
-        };
-        await deferred.promise;
-      }
-      if (exitCode == null) {
-        if (nodeShim.exitCode != null) {
-          exitCode = nodeShim.exitCode;
-        } else {
-          const getExitStatus = emMod["exitStatus"];
-          if (typeof getExitStatus === "function") {
-            exitCode = getExitStatus();
+        emMod["FS"]["chdir"](working);
+
+        exitCode = emMod["callMain"](msg.args);
+        if (nodeShim.createdWorker) {
+          const deferred = new Deferred();
+          nodeShim.onExit = (exitCode_: number) => {
+            exitCode = exitCode_;
+            deferred.resolve(0);
+          };
+          await deferred.promise;
+        }
+        if (exitCode == null) {
+          if (nodeShim.exitCode != null) {
+            exitCode = nodeShim.exitCode;
+          } else {
+            const getExitStatus = emMod["exitStatus"];
+            if (typeof getExitStatus === "function") {
+              exitCode = getExitStatus();
+            }
           }
         }
+      } else {
+        const args = [msg.program].concat(msg.args);
+        const importObject = {
+          ["wasi_snapshot_preview1"]: this.ctx.bind(wasi.snapshotPreview1),
+          ["jspawn"]: this.ctx.bind(wasi.jspawnCalls),
+        };
+        const instance = await WebAssembly.instantiate(mod, importObject);
+        this.ctx.fs = fs;
+        exitCode = this.ctx.start(instance, args, msg.env);
       }
-    } else {
-      const args = [msg.program].concat(msg.args);
-      const importObject = {
-        ["wasi_snapshot_preview1"]: this.ctx.bind(wasi.snapshotPreview1),
-      };
-      const instance = await WebAssembly.instantiate(mod, importObject);
-      exitCode = this.ctx.start(instance, args, msg.env);
+    } finally {
+      this.ctx.fs = this.fs;
+      fs.exit();
     }
 
     this.channel.pub(
@@ -322,10 +361,13 @@ class WorkerThread {
           );
           break;
         case FSRequestType.Mkdir:
-          ok = this.fs.mkdirSync(msg.args[0]);
+          ok = this.fs.mkdirSync(msg.args[0], { recursive: msg.args[1] });
           break;
         case FSRequestType.Readdir:
-          ok = this.fs.readdirSync(msg.args[0]);
+          ok = this.fs.readdirSync(msg.args[0], {
+            withFileTypes: msg.args[1],
+            recursive: msg.args[2],
+          });
           break;
         case FSRequestType.Rmdir:
           ok = this.fs.rmdirSync(msg.args[0], {
@@ -338,6 +380,64 @@ class WorkerThread {
         case FSRequestType.Chdir:
           ok = this.fs.chdir(msg.args[0]);
           break;
+        case FSRequestType.Chmod:
+          ok = this.fs.chmodSync(msg.args[0], msg.args[1]);
+          break;
+        case FSRequestType.Umask:
+          ok = this.fs.umask(msg.args[0]);
+          break;
+        case FSRequestType.Mkfifo:
+          ok = this.fs.mkfifoSync(msg.args[0]);
+          break;
+        case FSRequestType.SetMaxOpenFiles:
+          ok = this.fs.setMaxOpenFiles(msg.args[0]);
+          break;
+        case FSRequestType.FdUsage:
+          ok = this.fs.fdUsage();
+          break;
+        case FSRequestType.StdinWrite:
+          ok = this.fs.process(msg.args[0]).stdinWriteSync(msg.args[1]);
+          break;
+        case FSRequestType.StdinClose:
+          ok = this.fs.process(msg.args[0]).stdinCloseSync();
+          break;
+        case FSRequestType.StdioRead:
+          ok = this.fs.process(msg.args[0]).stdioReadSync(msg.args[1]);
+          break;
+        case FSRequestType.DestroyProcess:
+          ok = this.fs.process(msg.args[0]).destroy();
+          break;
+        case FSRequestType.Rename: {
+          let flags = 0;
+          if (msg.args[2]) {
+            flags |= constants["RENAME_NOREPLACE"];
+          }
+          if (msg.args[3]) {
+            flags |= constants["RENAME_EXCHANGE"];
+          }
+          ok = this.fs.renameSync(msg.args[0], msg.args[1], flags);
+          break;
+        }
+        case FSRequestType.Copy:
+          ok = this.fs.copySync(msg.args[0], msg.args[1], {
+            overwrite: msg.args[2],
+            preserve: msg.args[3],
+            follow: msg.args[4],
+          });
+          break;
+        case FSRequestType.Find:
+          ok = this.fs.findSync(msg.args[0], {
+            include: msg.args[1],
+            exclude: msg.args[2],
+            type: msg.args[3],
+            minSize: msg.args[4],
+            maxSize: msg.args[5],
+            minDepth: msg.args[6],
+            maxDepth: msg.args[7],
+            modifiedAfter: msg.args[8],
+            modifiedBefore: msg.args[9],
+          });
+          break;
       }
     } catch (err) {
       if (typeof err === "number") {
@@ -406,8 +506,9 @@ class Pthread {
     const data = (isNode() ? e : e.data) as any;
 
     if (Array.isArray(data)) {
-      const [fsModule, fsMemory] = data;
+      const [fsModule, fsMemory, pid] = data;
       const fs = await WasiFS.instantiate(fsModule, fsMemory);
+      fs.pid = pid;
       this.nodeShim = new NodeShim(fs.nodeFS(), fsModule, fsMemory, true);
 
       const workerPath = getWorkerData(JSPAWN_PTHREAD) as string;
@@ -25,6 +25,20 @@ describe("node ESM tests", function () {
     expect(outPNG.length).to.not.equal(0);
   });
 
+  it("refuses preopens for Emscripten programs", async function () {
+    let err;
+    try {
+      await venv.run(
+        "node_modules/@jspawn/imagemagick-wasm/magick.wasm",
+        ["-size", "100x100", "xc:white", "/out/blank_em.png"],
+        { preopens: [{ path: ".", name: "/out" }] }
+      );
+    } catch (e) {
+      err = e;
+    }
+    expect(err.message).to.contain("preopens");
+  });
+
   it("works with WASI program", async function () {
     const output = await venv.run(
       "node_modules/@jspawn/imagecli-wasm/imagecli.wasm",
@@ -3,6 +3,7 @@ import { expect } from "chai";
 import { dirname } from "path";
 import { fileURLToPath } from "url";
 import * as path from "path";
+import * as nodeFS from "fs/promises";
 
 const __dirname = dirname(fileURLToPath(import.meta.url));
 
@@ -23,7 +24,7 @@ describe("node fs tests", function () {
       bar: {},
     });
     const names = await venv.fs.readdir(".");
-    expect(names.length).to.equal(2);
+    expect(names.sort()).to.deep.equal(["bar", "dev", "foo"]);
   });
 
   it("mounts real", async function () {
@@ -47,4 +48,243 @@ describe("node fs tests", function () {
     const buf = await venv.fs.readFile("foo");
     expect(buf.byteLength > 0).to.be.true;
   });
+
+  it("refuses to overwrite read-only file", async function () {
+    await venv.fs.writeFile("foo", "foo");
+    await venv.fs.chmod("foo", 0o444);
+    let err;
+    try {
+      await venv.fs.writeFile("foo", "bar");
+    } catch (e) {
+      err = e;
+    }
+    expect(err.code).to.equal("EACCES");
+    await venv.fs.chmod("foo", 0o644);
+    await venv.fs.writeFile("foo", "bar");
+  });
+
+  it("treats .. at the root as the root", async function () {
+    await venv.fs.writeFile("foo", "foo");
+    const buf = await venv.fs.readFile("../../foo");
+    expect(new TextDecoder().decode(buf)).to.equal("foo");
+  });
+
+  it("refuses trailing slashes on files", async function () {
+    await venv.fs.writeFile("foo", "foo");
+    let err;
+    try {
+      await venv.fs.readFile("foo/");
+    } catch (e) {
+      err = e;
+    }
+    expect(err.code).to.equal("ENOTDIR");
+  });
+
+  it("refuses to write files over FIFOs", async function () {
+    await venv.fs.mkfifo("pipe");
+    let err;
+    try {
+      await venv.fs.writeFile("pipe", "foo");
+    } catch (e) {
+      err = e;
+    }
+    expect(err.code).to.equal("EINVAL");
+  });
+
+  it("writes files over devices", async function () {
+    await venv.fs.writeFile("dev/null", "foo");
+    const buf = await venv.fs.readFile("dev/null");
+    expect(buf.byteLength).to.equal(0);
+    let err;
+    try {
+      await venv.fs.writeFile("dev/stdout", "foo");
+    } catch (e) {
+      err = e;
+    }
+    expect(err.code).to.equal("EINVAL");
+  });
+
+  it("renames without replacing", async function () {
+    await venv.fs.writeFile("foo", "foo");
+    await venv.fs.writeFile("bar", "bar");
+    let err;
+    try {
+      await venv.fs.rename("foo", "bar", { noReplace: true });
+    } catch (e) {
+      err = e;
+    }
+    expect(err.code).to.equal("EEXIST");
+    await venv.fs.rename("foo", "baz", { noReplace: true });
+    const names = await venv.fs.readdir(".");
+    expect(names.sort()).to.deep.equal(["bar", "baz", "dev"]);
+  });
+
+  it("exchanges files and dirs", async function () {
+    await venv.fs.writeFile("foo", "foo");
+    await venv.fs.mkdir("bar");
+    await venv.fs.writeFile("bar/baz", "baz");
+    await venv.fs.rename("foo", "bar", { exchange: true });
+    const buf = await venv.fs.readFile("bar");
+    expect(new TextDecoder().decode(buf)).to.equal("foo");
+    expect(await venv.fs.readdir("foo")).to.deep.equal(["baz"]);
+    let err;
+    try {
+      await venv.fs.rename("foo", "qux", { exchange: true });
+    } catch (e) {
+      err = e;
+    }
+    expect(err.code).to.equal("ENOENT");
+  });
+
+  it("makes and lists dirs recursively", async function () {
+    await venv.fs.mkdir("a/b/c", { recursive: true });
+    await venv.fs.mkdir("a/b", { recursive: true });
+    await venv.fs.writeFile("a/b/c/file", "file");
+    await venv.fs.writeFile("a/top", "top");
+    expect(await venv.fs.readdir("a", { recursive: true })).to.deep.equal([
+      "b",
+      "b/c",
+      "b/c/file",
+      "top",
+    ]);
+    const dirents = await venv.fs.readdir("a", {
+      recursive: true,
+      withFileTypes: true,
+    });
+    expect(dirents.map((dirent) => dirent.name)).to.deep.equal([
+      "b",
+      "b/c",
+      "b/c/file",
+      "top",
+    ]);
+    expect(dirents[1].type).to.equal(3);
+    expect(dirents[2].type).to.equal(4);
+    let err;
+    try {
+      await venv.fs.mkdir("a/top", { recursive: true });
+    } catch (e) {
+      err = e;
+    }
+    expect(err.code).to.equal("EEXIST");
+    err = undefined;
+    try {
+      await venv.fs.mkdir("a/top/d", { recursive: true });
+    } catch (e) {
+      err = e;
+    }
+    expect(err.code).to.equal("ENOTDIR");
+    err = undefined;
+    try {
+      await venv.fs.mkdir("x/y");
+    } catch (e) {
+      err = e;
+    }
+    expect(err.code).to.equal("ENOENT");
+  });
+
+  it("copies dir trees", async function () {
+    // Gone before the copy, so it would fail if it fetched the URL.
+    const outDir = path.join(__dirname, "../out");
+    await nodeFS.mkdir(outDir, { recursive: true });
+    const gonePath = path.join(outDir, "copied.txt");
+    await nodeFS.writeFile(gonePath, "gone");
+    await venv.fs.mount(".", {
+      src: { sub: {}, real: fileURLToPath(import.meta.url), gone: gonePath },
+    });
+    await nodeFS.rm(gonePath);
+    await venv.fs.writeFile("src/a", "a");
+    await venv.fs.writeFile("src/sub/b", "b");
+    await venv.fs.copy("src", "dest");
+    expect((await venv.fs.readdir("dest")).sort()).to.deep.equal([
+      "a",
+      "gone",
+      "real",
+      "sub",
+    ]);
+    let buf = await venv.fs.readFile("dest/sub/b");
+    expect(new TextDecoder().decode(buf)).to.equal("b");
+    buf = await venv.fs.readFile("dest/real");
+    expect(buf.byteLength > 0).to.be.true;
+    // The copy is its own file.
+    await venv.fs.writeFile("dest/a", "changed");
+    buf = await venv.fs.readFile("src/a");
+    expect(new TextDecoder().decode(buf)).to.equal("a");
+    let err;
+    try {
+      await venv.fs.copy("src", "dest");
+    } catch (e) {
+      err = e;
+    }
+    expect(err.code).to.equal("EEXIST");
+    await venv.fs.copy("src", "dest", { overwrite: "skip" });
+    buf = await venv.fs.readFile("dest/a");
+    expect(new TextDecoder().decode(buf)).to.equal("changed");
+    await venv.fs.copy("src", "dest", { overwrite: "replace" });
+    buf = await venv.fs.readFile("dest/a");
+    expect(new TextDecoder().decode(buf)).to.equal("a");
+    err = undefined;
+    try {
+      await venv.fs.copy("src", "src/sub/src");
+    } catch (e) {
+      err = e;
+    }
+    expect(err.code).to.equal("EINVAL");
+  });
+
+  it("finds files", async function () {
+    await venv.fs.mount(".", {
+      out: { img: { deep: {} }, tmp: {} },
+    });
+    await venv.fs.writeFile("out/a.png", "aaaa");
+    await venv.fs.writeFile("out/b.txt", "b");
+    await venv.fs.writeFile("out/img/c.png", "c");
+    await venv.fs.writeFile("out/img/deep/d1.png", "dddd");
+    await venv.fs.writeFile("out/tmp/e.png", "e");
+    const paths = async (opts) =>
+      (await venv.fs.find("out", opts)).map((found) => found.path).sort();
+    expect(
+      await paths({ include: ["**/*.png"], exclude: ["tmp"] })
+    ).to.deep.equal(["a.png", "img/c.png", "img/deep/d1.png"]);
+    expect(await paths({ include: ["**/[a-c].png"] })).to.deep.equal([
+      "a.png",
+      "img/c.png",
+    ]);
+    expect(await paths({ type: "dir" })).to.deep.equal([
+      "img",
+      "img/deep",
+      "tmp",
+    ]);
+    expect(await paths({ type: "file", minSize: 2 })).to.deep.equal([
+      "a.png",
+      "img/deep/d1.png",
+    ]);
+    expect(await paths({ include: [], exclude: [], type: "file" })).to.deep.equal(
+      ["a.png", "b.txt", "img/c.png", "img/deep/d1.png", "tmp/e.png"]
+    );
+    expect(await paths({ type: "file", maxDepth: 1 })).to.deep.equal([
+      "a.png",
+      "b.txt",
+    ]);
+    expect(
+      await paths({ include: ["**/*.png"], modifiedAfter: new Date() })
+    ).to.deep.equal([]);
+    const found = await venv.fs.find("out", { include: ["b.txt"] });
+    expect(found[0].stats.size).to.equal(1);
+    let err;
+    try {
+      await venv.fs.find("out", { include: ["[a"] });
+    } catch (e) {
+      err = e;
+    }
+    expect(err.code).to.equal("EINVAL");
+  });
+
+  it("reports fd usage", async function () {
+    let usage = await venv.fs.fdUsage();
+    expect(usage.fds).to.deep.equal([0, 1, 2, 3]);
+    expect(usage.max).to.equal(1024);
+    await venv.fs.setMaxOpenFiles(16);
+    usage = await venv.fs.fdUsage();
+    expect(usage.max).to.equal(16);
+  });
 });
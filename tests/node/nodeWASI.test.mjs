@@ -6,6 +6,7 @@ import * as path from "path";
 import * as nodeFS from "fs/promises";
 import * as child_process from "child_process";
 import * as buffer from "buffer";
+import * as os from "os";
 
 globalThis.Blob = buffer.Blob;
 
@@ -21,19 +22,187 @@ describe("node wasi tests", function () {
     // Blocked by sync http
     // await runTest("fs_blob.rs");
   });
+
+  it("symlink", async function () {
+    await runTest("symlink.rs");
+  });
+
+  it("link", async function () {
+    await runTest("link.rs");
+  });
+
+  it("ino", async function () {
+    await runTest("ino.rs");
+  });
+
+  it("times", async function () {
+    await runTest("times.rs");
+  });
+
+  it("dev", async function () {
+    await runTest("dev.rs");
+  });
+
+  it("sparse", async function () {
+    await runTest("sparse.rs");
+  });
+
+  it("rights", async function () {
+    await runTest("rights.rs");
+  });
+
+  it("append", async function () {
+    await runTest("append.rs");
+  });
+
+  it("seek", async function () {
+    await runTest("seek.rs");
+  });
+
+  it("fds", async function () {
+    await runTest("fds.rs");
+  });
+
+  it("renumber", async function () {
+    await runTest("renumber.rs");
+  });
+
+  it("openat", async function () {
+    await runTest("openat.rs");
+  });
+
+  it("readdir", async function () {
+    await runTest("readdir.rs");
+  });
+
+  it("paths", async function () {
+    await runTest("paths.rs");
+  });
+
+  it("rename", async function () {
+    await runTest("rename.rs");
+  });
+
+  it("lock", async function () {
+    await runTest("lock.rs");
+  });
+
+  it("preopens", async function () {
+    const venv = await VirtualEnv.instantiate();
+    const program = await compile("preopens.rs");
+    await venv.fs.mount(".", { input: {}, output: {}, secret: {} });
+    await venv.fs.writeFile("input/a.txt", "a");
+    await venv.fs.writeFile("secret/key", "key");
+    const output = await venv.run(program, [], {
+      preopens: [
+        { path: "input", name: "/input", readonly: true },
+        { path: "output", name: "/output" },
+        { path: "output", name: "/clamped", clampDotDot: true },
+      ],
+    });
+    expect(output.exitCode).to.equal(0);
+    const buf = await venv.fs.readFile("output/b.txt");
+    expect(new TextDecoder().decode(buf)).to.equal("a");
+    venv.terminate();
+  });
+
+  it("truncate url", async function () {
+    const venv = await VirtualEnv.instantiate();
+    const program = await compile("truncate_url.rs");
+    const hostPath = path.join(__dirname, "../out/gone.txt");
+    await nodeFS.writeFile(hostPath, "old");
+    await venv.fs.mount(".", { gone: hostPath });
+    await nodeFS.rm(hostPath);
+    const output = await venv.run(program, []);
+    expect(output.exitCode).to.equal(0);
+    venv.terminate();
+  });
+
+  it("stdio", async function () {
+    const venv = await VirtualEnv.instantiate();
+    const program = await compile("stdio.rs");
+    let nchunks = 0;
+    const output = await venv.run(program, [], {
+      stdin: ["hello ", "world"],
+      onStdout: () => nchunks++,
+    });
+    expect(output.exitCode).to.equal(0);
+    expect(output.stdout).to.equal("hello world".repeat(10000));
+    expect(output.stderr).to.equal("hello world".repeat(10000) + "done");
+    expect(nchunks).to.be.above(1);
+    venv.terminate();
+  });
+
+  it("stdio with busy workers", async function () {
+    const venv = await VirtualEnv.instantiate();
+    const program = await compile("stdio.rs");
+    // More programs than workers, each writing more than fits in its pipe.
+    const outputs = await Promise.all(
+      Array.from({ length: os.cpus().length + 1 }, () =>
+        venv.run(program, [], { stdin: ["hello ", "world"] })
+      )
+    );
+    for (const output of outputs) {
+      expect(output.exitCode).to.equal(0);
+      expect(output.stdout).to.equal("hello world".repeat(10000));
+    }
+    venv.terminate();
+  });
+
+  it("process", async function () {
+    const venv = await VirtualEnv.instantiate();
+    const program = await compile("close_stdio.rs");
+    for (let i = 0; i < 2; i++) {
+      const output = await venv.run(program, []);
+      expect(output.exitCode).to.equal(0);
+    }
+    const usage = await venv.fs.fdUsage();
+    expect(usage.fds).to.deep.equal([0, 1, 2, 3]);
+    venv.terminate();
+  });
+
+  it("fifo", async function () {
+    const venv = await VirtualEnv.instantiate();
+    const writer = await compile("fifo_writer.rs");
+    const reader = await compile("fifo_reader.rs");
+    await venv.fs.mkfifo("pipe");
+    const outputs = await Promise.all([
+      venv.run(writer, []),
+      venv.run(reader, []),
+    ]);
+    for (const output of outputs) {
+      expect(output.exitCode).to.equal(0);
+    }
+    venv.terminate();
+  });
 });
 
 async function runTest(name) {
   const venv = await VirtualEnv.instantiate();
 
+  const testPath = path.join(__dirname, "../wasi", name);
+  const src = (await nodeFS.readFile(testPath)).toString();
+  const init = parseInit(src);
+  const outPath = await compile(name);
+
+  if (init.fs) {
+    await venv.fs.mount(".", init.fs);
+  }
+
+  const output = await venv.run(outPath, init.args || [], {
+    env: init.env,
+  });
+  expect(output.exitCode).to.equal(0);
+
+  venv.terminate();
+}
+
+async function compile(name) {
   const outDir = path.join(__dirname, "../out");
   await nodeFS.mkdir(outDir, { recursive: true });
   const testPath = path.join(__dirname, "../wasi", name);
   const outPath = path.join(outDir, name.split(".")[0] + ".wasm");
 
-  const src = (await nodeFS.readFile(testPath)).toString();
-  const init = parseInit(src);
-
   await new Promise((resolve, reject) => {
     const child = child_process.spawn("rustc", [
       testPath,
@@ -61,16 +230,7 @@ async function runTest(name) {
     });
   });
 
-  if (init.fs) {
-    await venv.fs.mount(".", init.fs);
-  }
-
-  const output = await venv.run(outPath, init.args || [], {
-    env: init.env,
-  });
-  expect(output.exitCode).to.equal(0);
-
-  venv.terminate();
+  return outPath;
 }
 
 function parseInit(s) {
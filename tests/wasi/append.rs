@@ -0,0 +1,31 @@
+// ```json
+// {
+//   "fs": { "foo": {} }
+// }
+// ```
+
+use std::fs;
+use std::io::{Seek, SeekFrom, Write};
+
+fn main() {
+    fs::write("foo/log", b"a").unwrap();
+
+    // Seeking doesn't stop appends from landing at the end.
+    let mut file = fs::OpenOptions::new()
+        .append(true)
+        .open("foo/log")
+        .unwrap();
+    file.seek(SeekFrom::Start(0)).unwrap();
+    file.write_all(b"b").unwrap();
+
+    // Two descriptors appending to the same file don't overwrite each other.
+    let mut other = fs::OpenOptions::new()
+        .append(true)
+        .open("foo/log")
+        .unwrap();
+    file.write_all(b"c").unwrap();
+    other.write_all(b"d").unwrap();
+    file.write_all(b"e").unwrap();
+
+    assert_eq!(fs::read("foo/log").unwrap(), b"abcde");
+}
@@ -0,0 +1,15 @@
+use std::fs;
+use std::os::wasi::io::IntoRawFd;
+
+#[link(wasm_import_module = "wasi_snapshot_preview1")]
+extern "C" {
+    fn fd_close(fd: u32) -> u16;
+}
+
+fn main() {
+    // Closing stdout only affects this program, so this passes every time it's run.
+    assert_eq!(unsafe { fd_close(1) }, 0);
+    assert_eq!(fs::File::open(".").unwrap().into_raw_fd(), 1);
+    // Left open on purpose: exiting releases it.
+    std::mem::forget(fs::File::open(".").unwrap());
+}
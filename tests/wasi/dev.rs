@@ -0,0 +1,46 @@
+use std::fs;
+use std::io::{Read, Write};
+use std::os::wasi::fs::FileTypeExt;
+
+fn main() {
+    // Writes to `/dev/null` vanish and reads are empty.
+    fs::write("/dev/null", b"foo").unwrap();
+    let mut buf = Vec::new();
+    fs::File::open("/dev/null")
+        .unwrap()
+        .read_to_end(&mut buf)
+        .unwrap();
+    assert!(buf.is_empty());
+
+    // Truncating doesn't turn a device into a file.
+    fs::File::create("/dev/null")
+        .unwrap()
+        .write_all(b"foo")
+        .unwrap();
+    assert!(fs::metadata("/dev/null")
+        .unwrap()
+        .file_type()
+        .is_char_device());
+
+    let mut buf = [1; 64];
+    fs::File::open("/dev/zero")
+        .unwrap()
+        .read_exact(&mut buf)
+        .unwrap();
+    assert!(buf.iter().all(|b| *b == 0));
+
+    let mut buf = [0; 64];
+    fs::File::open("/dev/urandom")
+        .unwrap()
+        .read_exact(&mut buf)
+        .unwrap();
+    assert!(buf.iter().any(|b| *b != 0));
+
+    let names = fs::read_dir("/dev")
+        .unwrap()
+        .map(|ent| ent.unwrap().file_name().into_string().unwrap())
+        .collect::<Vec<_>>();
+    for name in ["null", "zero", "urandom", "stdin", "stdout", "stderr"] {
+        assert!(names.iter().any(|x| x == name));
+    }
+}
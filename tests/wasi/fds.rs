@@ -0,0 +1,34 @@
+// ```json
+// {
+//   "fs": { "foo": {} }
+// }
+// ```
+
+use std::fs;
+use std::os::wasi::io::AsRawFd;
+
+fn main() {
+    fs::write("foo/bar", b"").unwrap();
+
+    // A closed descriptor is handed out again before any higher one.
+    let a = fs::File::open("foo/bar").unwrap();
+    let b = fs::File::open("foo/bar").unwrap();
+    let a_fd = a.as_raw_fd();
+    assert!(b.as_raw_fd() > a_fd);
+    drop(a);
+    let c = fs::File::open("foo/bar").unwrap();
+    assert_eq!(c.as_raw_fd(), a_fd);
+
+    // Opening past the limit fails instead of growing forever.
+    let mut files = Vec::new();
+    let err = loop {
+        match fs::File::open("foo/bar") {
+            Ok(file) => files.push(file),
+            Err(err) => break err,
+        }
+    };
+    assert_eq!(err.raw_os_error(), Some(33));
+    assert!(files.len() < 1024);
+    files.pop();
+    fs::File::open("foo/bar").unwrap();
+}
@@ -0,0 +1,18 @@
+// Used by the "fifo" test together with `fifo_writer.rs`.
+
+use std::fs;
+use std::io::Read;
+use std::os::wasi::fs::FileExt;
+
+fn main() {
+    let mut pipe = fs::File::open("pipe").unwrap();
+    // Pipes have no position to read at (ESPIPE).
+    let mut buf = [0];
+    assert_eq!(pipe.read_at(&mut buf, 0).err().unwrap().raw_os_error(), Some(70));
+    let mut buf = Vec::new();
+    pipe.read_to_end(&mut buf).unwrap();
+    assert_eq!(buf.len(), 256 * 1024);
+    for (i, chunk) in buf.chunks(1024).enumerate() {
+        assert!(chunk.iter().all(|b| *b == i as u8));
+    }
+}
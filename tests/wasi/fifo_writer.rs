@@ -0,0 +1,15 @@
+// Used by the "fifo" test together with `fifo_reader.rs`.
+
+use std::fs;
+use std::io::Write;
+use std::os::wasi::fs::FileExt;
+
+fn main() {
+    let mut pipe = fs::OpenOptions::new().write(true).open("pipe").unwrap();
+    // Pipes have no position to write at (ESPIPE).
+    assert_eq!(pipe.write_at(b"x", 0).err().unwrap().raw_os_error(), Some(70));
+    // More than fits in the FIFO at once.
+    for i in 0..256 {
+        pipe.write_all(&[i as u8; 1024]).unwrap();
+    }
+}
@@ -0,0 +1,56 @@
+// ```json
+// {
+//   "fs": { "foo": {} }
+// }
+// ```
+
+use std::fs;
+use std::os::wasi::fs::{DirEntryExt, MetadataExt};
+
+fn main() {
+    fs::write("foo/file", b"foo").unwrap();
+    fs::create_dir("foo/dir").unwrap();
+    let file = fs::metadata("foo/file").unwrap();
+    let dir = fs::metadata("foo/dir").unwrap();
+    assert_ne!(file.ino(), dir.ino());
+    assert_ne!(file.ino(), 0);
+    // Everything is on the same device.
+    assert_eq!(file.dev(), dir.dev());
+    assert_eq!(file.dev(), fs::metadata(".").unwrap().dev());
+
+    // Renaming keeps the inode, even into another dir.
+    fs::rename("foo/file", "foo/dir/moved").unwrap();
+    let moved = fs::metadata("foo/dir/moved").unwrap();
+    assert_eq!(moved.ino(), file.ino());
+    assert_eq!(moved.dev(), file.dev());
+    fs::rename("foo/dir", "foo/dir2").unwrap();
+    assert_eq!(fs::metadata("foo/dir2").unwrap().ino(), dir.ino());
+
+    // Hard links share it, and count each other.
+    fs::hard_link("foo/dir2/moved", "foo/link").unwrap();
+    let link = fs::metadata("foo/link").unwrap();
+    assert_eq!(link.ino(), file.ino());
+    assert_eq!(link.dev(), file.dev());
+    assert_eq!(link.nlink(), 2);
+    assert_eq!(fs::metadata("foo/dir2/moved").unwrap().nlink(), 2);
+    fs::remove_file("foo/dir2/moved").unwrap();
+    assert_eq!(fs::metadata("foo/link").unwrap().nlink(), 1);
+    assert_eq!(fs::metadata("foo/link").unwrap().ino(), file.ino());
+
+    // An open file reports the same as its path.
+    let opened = fs::File::open("foo/link").unwrap().metadata().unwrap();
+    assert_eq!(opened.ino(), file.ino());
+    assert_eq!(opened.dev(), file.dev());
+    assert_eq!(opened.nlink(), 1);
+
+    // So do dir entries.
+    let mut names = vec![];
+    for ent in fs::read_dir("foo").unwrap() {
+        let ent = ent.unwrap();
+        let stat = fs::symlink_metadata(ent.path()).unwrap();
+        assert_eq!(ent.ino(), stat.ino());
+        names.push(ent.file_name().into_string().unwrap());
+    }
+    names.sort();
+    assert_eq!(names, ["dir2", "link"]);
+}
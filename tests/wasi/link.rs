@@ -0,0 +1,47 @@
+// ```json
+// {
+//   "fs": { "foo": {} }
+// }
+// ```
+
+use std::fs;
+use std::io::{Read, Write};
+
+fn main() {
+    fs::File::create("foo/file")
+        .unwrap()
+        .write_all(b"foo bar baz")
+        .unwrap();
+
+    assert!(fs::hard_link("foo/file", "foo/file_link").is_ok());
+
+    // Existing names can't be reused.
+    assert!(fs::hard_link("foo/file", "foo/file_link").is_err());
+
+    // Dirs can't be hard linked.
+    assert!(fs::create_dir("foo/bar").is_ok());
+    assert!(fs::hard_link("foo/bar", "foo/bar_link").is_err());
+
+    // Both names refer to the same file.
+    fs::OpenOptions::new()
+        .append(true)
+        .open("foo/file_link")
+        .unwrap()
+        .write_all(b" qux")
+        .unwrap();
+    assert_eq!(read("foo/file"), "foo bar baz qux");
+
+    // Removing one name leaves the other working.
+    assert!(fs::remove_file("foo/file").is_ok());
+    assert!(fs::metadata("foo/file").is_err());
+    assert_eq!(read("foo/file_link"), "foo bar baz qux");
+}
+
+fn read(path: &str) -> String {
+    let mut s = String::new();
+    fs::File::open(path)
+        .unwrap()
+        .read_to_string(&mut s)
+        .unwrap();
+    s
+}
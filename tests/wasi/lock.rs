@@ -0,0 +1,73 @@
+// ```json
+// {
+//   "fs": { "foo": {} }
+// }
+// ```
+
+use std::fs;
+use std::os::wasi::io::AsRawFd;
+
+#[link(wasm_import_module = "jspawn")]
+extern "C" {
+    fn fd_lock(fd: u32, exclusive: u32, start: u64, len: u64, nonblock: u32) -> u16;
+    fn fd_unlock(fd: u32, start: u64, len: u64) -> u16;
+}
+
+// Errnos
+const AGAIN: u16 = 6;
+const BADF: u16 = 8;
+
+fn lock(file: &fs::File, exclusive: bool, start: u64, len: u64) -> u16 {
+    unsafe { fd_lock(file.as_raw_fd() as u32, exclusive as u32, start, len, 0) }
+}
+
+fn try_lock(file: &fs::File, exclusive: bool, start: u64, len: u64) -> u16 {
+    unsafe { fd_lock(file.as_raw_fd() as u32, exclusive as u32, start, len, 1) }
+}
+
+fn unlock(file: &fs::File, start: u64, len: u64) -> u16 {
+    unsafe { fd_unlock(file.as_raw_fd() as u32, start, len) }
+}
+
+fn open() -> fs::File {
+    fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open("foo/bar")
+        .unwrap()
+}
+
+fn main() {
+    fs::write("foo/bar", b"foo").unwrap();
+
+    // Locks belong to the open file, so opening it again makes another owner.
+    let a = open();
+    let b = open();
+    assert_eq!(lock(&a, true, 0, 0), 0);
+    assert_eq!(try_lock(&b, false, 0, 1), AGAIN);
+    assert_eq!(try_lock(&b, true, 100, 1), AGAIN);
+
+    // Unlocking part of it lets others lock that part.
+    assert_eq!(unlock(&a, 0, 10), 0);
+    assert_eq!(try_lock(&b, true, 0, 10), 0);
+    assert_eq!(try_lock(&b, false, 10, 1), AGAIN);
+    assert_eq!(unlock(&a, 0, 0), 0);
+    assert_eq!(unlock(&b, 0, 0), 0);
+
+    // Shared locks only keep out exclusive ones.
+    assert_eq!(lock(&a, false, 0, 0), 0);
+    assert_eq!(try_lock(&b, false, 0, 0), 0);
+    assert_eq!(try_lock(&a, true, 0, 0), AGAIN);
+
+    // Closing a descriptor releases its locks.
+    drop(b);
+    assert_eq!(try_lock(&a, true, 0, 0), 0);
+    let b = open();
+    assert_eq!(try_lock(&b, false, 0, 0), AGAIN);
+    drop(a);
+    assert_eq!(try_lock(&b, true, 0, 0), 0);
+
+    // Like `fcntl`, an exclusive lock needs the file open for writing.
+    let c = fs::File::open("foo/bar").unwrap();
+    assert_eq!(try_lock(&c, true, 0, 0), BADF);
+}
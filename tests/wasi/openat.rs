@@ -0,0 +1,117 @@
+// ```json
+// {
+//   "fs": { "foo": {} }
+// }
+// ```
+
+use std::convert::TryInto;
+use std::fs;
+use std::io::Read;
+use std::os::wasi::io::{FromRawFd, IntoRawFd};
+
+#[link(wasm_import_module = "wasi_snapshot_preview1")]
+extern "C" {
+    fn path_open(
+        fd: u32,
+        dirflags: u32,
+        path_ptr: *const u8,
+        path_len: usize,
+        oflags: u32,
+        fs_rights_base: u64,
+        fs_rights_inheriting: u64,
+        fdflags: u32,
+        opened_fd: *mut u32,
+    ) -> u16;
+    fn fd_readdir(fd: u32, buf: *mut u8, buf_len: usize, cookie: u64, buf_used: *mut usize) -> u16;
+    fn path_symlink(
+        old_path_ptr: *const u8,
+        old_path_len: usize,
+        fd: u32,
+        new_path_ptr: *const u8,
+        new_path_len: usize,
+    ) -> u16;
+}
+
+const DIRENT_SIZE: usize = 24;
+
+fn open_at(dir: u32, path: &str) -> Result<fs::File, u16> {
+    let mut fd = 0;
+    let errno = unsafe { path_open(dir, 0, path.as_ptr(), path.len(), 0, !0, !0, 0, &mut fd) };
+    if errno == 0 {
+        Ok(unsafe { fs::File::from_raw_fd(fd as i32) })
+    } else {
+        Err(errno)
+    }
+}
+
+fn main() {
+    fs::create_dir("foo/dir").unwrap();
+    fs::write("foo/dir/bar", b"bar").unwrap();
+    fs::write("foo/baz", b"baz").unwrap();
+    let dir = fs::File::open("foo/dir").unwrap().into_raw_fd() as u32;
+
+    // Relative to the dir rather than the cwd.
+    assert_eq!(read_at(dir, "bar"), "bar");
+
+    // Going up is fine as long as it stays in the preopen.
+    assert_eq!(read_at(dir, "../baz"), "baz");
+    // ENOTCAPABLE
+    assert_eq!(open_at(dir, "../../../baz").err(), Some(76));
+    assert_eq!(open_at(dir, "/foo/baz").err(), Some(76));
+
+    // A file isn't a dir to resolve against. (ENOTDIR)
+    let file = fs::File::open("foo/baz").unwrap().into_raw_fd() as u32;
+    assert_eq!(open_at(file, "bar").err(), Some(54));
+
+    // The dir is wherever it was moved, and `..` is its new parent.
+    fs::create_dir("foo/other").unwrap();
+    fs::rename("foo/dir", "foo/other/moved").unwrap();
+    assert_eq!(read_at(dir, "bar"), "bar");
+    assert_eq!(read_at(dir, "../../baz"), "baz");
+    let other = fs::File::open("foo/other").unwrap().into_raw_fd() as u32;
+    assert_eq!(dot_inos(dir).1, dot_inos(other).0);
+    // Even once something else is where it was opened.
+    fs::create_dir("foo/dir").unwrap();
+    fs::write("foo/dir/bar", b"new").unwrap();
+    assert_eq!(read_at(dir, "bar"), "bar");
+
+    // A dir opened through a symlink stays the one it pointed to then.
+    symlink("other/moved", "foo/link");
+    let link = fs::File::open("foo/link").unwrap().into_raw_fd() as u32;
+    fs::remove_file("foo/link").unwrap();
+    symlink("dir", "foo/link");
+    assert_eq!(read_at(link, "bar"), "bar");
+
+    // Nothing's left to resolve against once the dir is removed. ENOENT
+    fs::create_dir("foo/gone").unwrap();
+    let gone = fs::File::open("foo/gone").unwrap().into_raw_fd() as u32;
+    fs::remove_dir("foo/gone").unwrap();
+    assert_eq!(open_at(gone, "bar").err(), Some(44));
+}
+
+fn read_at(dir: u32, path: &str) -> String {
+    let mut buf = String::new();
+    open_at(dir, path)
+        .unwrap()
+        .read_to_string(&mut buf)
+        .unwrap();
+    buf
+}
+
+// The inodes of `.` and `..`, which `fd_readdir` lists first.
+fn dot_inos(dir: u32) -> (u64, u64) {
+    let mut buf = [0; 256];
+    let mut buf_used = 0;
+    let errno = unsafe { fd_readdir(dir, buf.as_mut_ptr(), buf.len(), 0, &mut buf_used) };
+    assert_eq!(errno, 0);
+    let ino = |pos: usize| u64::from_le_bytes(buf[pos + 8..pos + 16].try_into().unwrap());
+    // Both names are short enough that the second entry follows the first right away.
+    (ino(0), ino(DIRENT_SIZE + 1))
+}
+
+fn symlink(target: &str, path: &str) {
+    // The `/` preopen.
+    let errno =
+        unsafe { path_symlink(target.as_ptr(), target.len(), 3, path.as_ptr(), path.len()) };
+    assert_eq!(errno, 0);
+}
@@ -0,0 +1,114 @@
+// ```json
+// {
+//   "fs": { "foo": {} }
+// }
+// ```
+
+use std::fs;
+use std::os::wasi::io::IntoRawFd;
+
+#[link(wasm_import_module = "wasi_snapshot_preview1")]
+extern "C" {
+    fn path_open(
+        fd: u32,
+        dirflags: u32,
+        path_ptr: *const u8,
+        path_len: usize,
+        oflags: u32,
+        fs_rights_base: u64,
+        fs_rights_inheriting: u64,
+        fdflags: u32,
+        opened_fd: *mut u32,
+    ) -> u16;
+    fn path_create_directory(fd: u32, path_ptr: *const u8, path_len: usize) -> u16;
+}
+
+// Oflags
+const CREAT: u32 = 1;
+const DIRECTORY: u32 = 2;
+
+fn open_at(dir: u32, path: &[u8], oflags: u32) -> Result<u32, u16> {
+    let mut fd = 0;
+    let errno =
+        unsafe { path_open(dir, 0, path.as_ptr(), path.len(), oflags, !0, !0, 0, &mut fd) };
+    if errno == 0 {
+        Ok(fd)
+    } else {
+        Err(errno)
+    }
+}
+
+fn errno(res: std::io::Result<impl Sized>) -> i32 {
+    res.err().unwrap().raw_os_error().unwrap()
+}
+
+fn main() {
+    fs::create_dir("foo/dir").unwrap();
+    fs::write("foo/file", b"file").unwrap();
+    let dir = fs::File::open("foo").unwrap().into_raw_fd() as u32;
+
+    // A trailing slash only works for dirs. ENOTDIR
+    assert!(fs::metadata("foo/dir/").unwrap().is_dir());
+    assert!(fs::metadata("foo/dir/.").unwrap().is_dir());
+    assert_eq!(errno(fs::metadata("foo/file/")), 54);
+    assert_eq!(errno(fs::read("foo/file/")), 54);
+    assert_eq!(errno(fs::remove_file("foo/file/")), 54);
+    assert_eq!(errno(fs::rename("foo/file/", "foo/moved")), 54);
+    assert_eq!(errno(fs::rename("foo/file", "foo/moved/")), 54);
+    // Creating a file that way is EISDIR, like Linux.
+    assert_eq!(open_at(dir, b"new/", CREAT).err(), Some(31));
+    fs::create_dir("foo/new_dir/").unwrap();
+    fs::remove_dir("foo/new_dir/").unwrap();
+
+    // `..` is the parent of wherever the path got to, which isn't where the symlink is.
+    fs::create_dir("foo/dir/sub").unwrap();
+    symlink("dir/sub", "foo/link");
+    assert!(fs::metadata("foo/link/../sub").unwrap().is_dir());
+    // And it has to get somewhere first. ENOTDIR, ENOENT
+    assert_eq!(errno(fs::metadata("foo/file/..")), 54);
+    assert_eq!(errno(fs::metadata("foo/missing/..")), 44);
+    // An empty path isn't the dir itself. ENOENT
+    assert_eq!(open_at(dir, b"", 0).err(), Some(44));
+
+    // ENAMETOOLONG
+    let name = "a".repeat(255);
+    fs::write(format!("foo/{name}"), b"").unwrap();
+    assert_eq!(errno(fs::write(format!("foo/{name}a"), b"")), 37);
+    let path = "./".repeat(2048);
+    assert_eq!(open_at(dir, path.as_bytes(), 0).err(), Some(37));
+
+    // EINVAL, there's no way to pass these in C.
+    assert_eq!(open_at(dir, b"file\0", 0).err(), Some(28));
+
+    // It's the path as given that can't be longer than PATH_MAX, so a short one works
+    // in a dir that's deeper than that.
+    let sub = "d".repeat(200);
+    let mut deep = dir;
+    for _ in 0..21 {
+        assert_eq!(
+            unsafe { path_create_directory(deep, sub.as_ptr(), sub.len()) },
+            0
+        );
+        deep = open_at(deep, sub.as_bytes(), DIRECTORY).unwrap();
+    }
+    open_at(deep, b"file", CREAT).unwrap();
+    open_at(deep, b"file", 0).unwrap();
+}
+
+fn symlink(target: &str, path: &str) {
+    // The `/` preopen.
+    let errno =
+        unsafe { path_symlink(target.as_ptr(), target.len(), 3, path.as_ptr(), path.len()) };
+    assert_eq!(errno, 0);
+
+    #[link(wasm_import_module = "wasi_snapshot_preview1")]
+    extern "C" {
+        fn path_symlink(
+            old_path_ptr: *const u8,
+            old_path_len: usize,
+            fd: i32,
+            new_path_ptr: *const u8,
+            new_path_len: usize,
+        ) -> u16;
+    }
+}
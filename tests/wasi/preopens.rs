@@ -0,0 +1,76 @@
+// Run with `/input` (read-only) and `/output` preopened instead of the cwd, and `/output`
+// again as `/clamped` where `..` stays put, see nodeWASI.test.mjs.
+
+use std::fs;
+use std::io::Write;
+
+// The preopens come right after stdio, in the order they were given.
+const OUTPUT_FD: i32 = 4;
+
+fn main() {
+    // Nothing outside of the preopens is visible, except for the devices.
+    assert!(fs::metadata("/secret/key").is_err());
+    assert!(fs::metadata("/input/../secret/key").is_err());
+    fs::write("/dev/null", b"foo").unwrap();
+    assert!(fs::metadata("/dev/../secret/key").is_err());
+
+    // `/input` can only be read.
+    let buf = fs::read("/input/a.txt").unwrap();
+    assert_eq!(buf, b"a");
+    assert!(fs::write("/input/b.txt", b"b").is_err());
+    assert!(fs::remove_file("/input/a.txt").is_err());
+    assert!(fs::create_dir("/input/dir").is_err());
+    assert!(fs::OpenOptions::new()
+        .write(true)
+        .open("/input/a.txt")
+        .and_then(|mut file| file.write_all(b"b"))
+        .is_err());
+    assert_eq!(fs::read("/input/a.txt").unwrap(), b"a");
+
+    // `/output` can be written.
+    fs::write("/output/b.txt", &buf).unwrap();
+    fs::create_dir("/output/dir").unwrap();
+
+    // Symlinks can't leave the preopen either, and absolute ones start from it.
+    symlink("../secret/key", "escape");
+    assert!(fs::read("/output/escape").is_err());
+    symlink("/b.txt", "abs");
+    assert_eq!(fs::read("/output/abs").unwrap(), b"a");
+    symlink("/../secret/key", "abs_escape");
+    assert!(fs::read("/output/abs_escape").is_err());
+
+    // And neither can renames or links.
+    assert!(fs::rename("/output/b.txt", "/input/b.txt").is_err());
+    assert!(fs::hard_link("/input/a.txt", "/output/a.txt").is_err());
+
+    // `..` fails at the root of a preopen, unless it was asked to stay put. ENOTCAPABLE
+    let err = fs::read("/output/../b.txt").err().unwrap();
+    assert_eq!(err.raw_os_error(), Some(76));
+    assert_eq!(fs::read("/clamped/../b.txt").unwrap(), b"a");
+    assert_eq!(fs::read("/clamped/dir/../../../b.txt").unwrap(), b"a");
+    assert!(fs::read("/clamped/../../secret/key").is_err());
+}
+
+fn symlink(target: &str, path: &str) {
+    let errno = unsafe {
+        path_symlink(
+            target.as_ptr(),
+            target.len(),
+            OUTPUT_FD,
+            path.as_ptr(),
+            path.len(),
+        )
+    };
+    assert_eq!(errno, 0);
+
+    #[link(wasm_import_module = "wasi_snapshot_preview1")]
+    extern "C" {
+        fn path_symlink(
+            old_path_ptr: *const u8,
+            old_path_len: usize,
+            fd: i32,
+            new_path_ptr: *const u8,
+            new_path_len: usize,
+        ) -> u16;
+    }
+}
@@ -0,0 +1,71 @@
+// ```json
+// {
+//   "fs": { "foo": {} }
+// }
+// ```
+
+use std::convert::TryInto;
+use std::fs;
+use std::os::wasi::io::IntoRawFd;
+
+#[link(wasm_import_module = "wasi_snapshot_preview1")]
+extern "C" {
+    fn fd_readdir(fd: u32, buf: *mut u8, buf_len: usize, cookie: u64, buf_used: *mut usize)
+        -> u16;
+}
+
+const DIRENT_SIZE: usize = 24;
+
+fn main() {
+    fs::create_dir("foo/dir").unwrap();
+    let mut expected = (0..300).map(|i| i.to_string()).collect::<Vec<_>>();
+    for name in &expected {
+        fs::write(format!("foo/dir/{name}"), b"").unwrap();
+    }
+    expected.sort();
+
+    // libc lists it a few entries at a time.
+    let mut names = fs::read_dir("foo/dir")
+        .unwrap()
+        .map(|ent| ent.unwrap().file_name().into_string().unwrap())
+        .collect::<Vec<_>>();
+    names.sort();
+    assert_eq!(names, expected);
+
+    // With a tiny buffer, and removing what was already listed halfway through.
+    let dir = fs::File::open("foo/dir").unwrap().into_raw_fd() as u32;
+    let mut cookie = 0;
+    let mut names = Vec::new();
+    let mut removed = false;
+    loop {
+        let mut buf = [0; 64];
+        let mut buf_used = 0;
+        let errno = unsafe { fd_readdir(dir, buf.as_mut_ptr(), buf.len(), cookie, &mut buf_used) };
+        assert_eq!(errno, 0);
+        let mut pos = 0;
+        while pos + DIRENT_SIZE <= buf_used {
+            let next = u64::from_le_bytes(buf[pos..pos + 8].try_into().unwrap());
+            let len = u32::from_le_bytes(buf[pos + 16..pos + 20].try_into().unwrap()) as usize;
+            if pos + DIRENT_SIZE + len > buf_used {
+                break;
+            }
+            let name = &buf[pos + DIRENT_SIZE..pos + DIRENT_SIZE + len];
+            names.push(String::from_utf8(name.to_vec()).unwrap());
+            cookie = next;
+            pos += DIRENT_SIZE + len;
+        }
+        if buf_used < buf.len() {
+            break;
+        }
+        if !removed && names.len() > 100 {
+            for name in &names[2..] {
+                fs::remove_file(format!("foo/dir/{name}")).unwrap();
+            }
+            removed = true;
+        }
+    }
+    assert_eq!(names[..2], [".", ".."]);
+    let mut names = names[2..].to_vec();
+    names.sort();
+    assert_eq!(names, expected);
+}
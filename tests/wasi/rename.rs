@@ -0,0 +1,118 @@
+// ```json
+// {
+//   "fs": { "foo": {} }
+// }
+// ```
+
+use std::fs;
+use std::os::wasi::io::AsRawFd;
+
+#[link(wasm_import_module = "wasi_snapshot_preview1")]
+extern "C" {
+    fn fd_readdir(fd: u32, buf: *mut u8, buf_len: usize, cookie: u64, bufused: *mut usize) -> u16;
+}
+
+fn main() {
+    fs::write("foo/a", b"a").unwrap();
+    fs::write("foo/b", b"b").unwrap();
+
+    // Files are replaced, leaving a single entry.
+    fs::rename("foo/a", "foo/b").unwrap();
+    assert_eq!(fs::read("foo/b").unwrap(), b"a");
+    assert_eq!(names("foo"), ["b"]);
+    // ENOENT
+    assert_eq!(errno(fs::rename("foo/a", "foo/c")), 44);
+
+    // Renaming a file onto itself (or another link to it) does nothing.
+    fs::rename("foo/b", "foo/b").unwrap();
+    fs::hard_link("foo/b", "foo/b_link").unwrap();
+    fs::rename("foo/b", "foo/b_link").unwrap();
+    assert_eq!(names("foo"), ["b", "b_link"]);
+    fs::remove_file("foo/b_link").unwrap();
+
+    // Dirs can only replace empty dirs.
+    fs::create_dir("foo/dir").unwrap();
+    fs::write("foo/dir/x", b"x").unwrap();
+    fs::create_dir("foo/empty").unwrap();
+    fs::create_dir("foo/full").unwrap();
+    fs::write("foo/full/y", b"y").unwrap();
+    // ENOTDIR, EISDIR, ENOTEMPTY
+    assert_eq!(errno(fs::rename("foo/dir", "foo/b")), 54);
+    assert_eq!(errno(fs::rename("foo/b", "foo/empty")), 31);
+    assert_eq!(errno(fs::rename("foo/dir", "foo/full")), 55);
+    fs::rename("foo/dir", "foo/empty").unwrap();
+    assert_eq!(fs::read("foo/empty/x").unwrap(), b"x");
+    assert_eq!(names("foo"), ["b", "empty", "full"]);
+
+    // Dirs can't be moved into themselves, not even through a symlink. EINVAL
+    fs::create_dir("foo/empty/sub").unwrap();
+    assert_eq!(errno(fs::rename("foo/empty", "foo/empty/sub/dir")), 28);
+    assert_eq!(errno(fs::rename("foo/empty", "foo/empty/sub")), 28);
+    symlink("empty/sub", "foo/sub_link");
+    assert_eq!(errno(fs::rename("foo/empty", "foo/sub_link/dir")), 28);
+
+    // Moving a dir takes everything in it along.
+    fs::rename("foo/empty", "foo/full/moved").unwrap();
+    assert_eq!(fs::read("foo/full/moved/x").unwrap(), b"x");
+    assert!(fs::metadata("foo/full/moved/sub").unwrap().is_dir());
+
+    // A dir replaced while it's open is gone for whatever has it open, like one that's
+    // removed. ENOENT
+    fs::create_dir("foo/open").unwrap();
+    fs::create_dir("foo/new").unwrap();
+    let open = fs::File::open("foo/open").unwrap();
+    fs::rename("foo/new", "foo/open").unwrap();
+    assert_eq!(readdir(&open), Err(44));
+    let removed = fs::File::open("foo/open").unwrap();
+    fs::remove_dir("foo/open").unwrap();
+    assert_eq!(readdir(&removed), Err(44));
+}
+
+fn readdir(dir: &fs::File) -> Result<usize, u16> {
+    let mut buf = [0; 256];
+    let mut bufused = 0;
+    let errno = unsafe {
+        fd_readdir(
+            dir.as_raw_fd() as u32,
+            buf.as_mut_ptr(),
+            buf.len(),
+            0,
+            &mut bufused,
+        )
+    };
+    if errno == 0 {
+        Ok(bufused)
+    } else {
+        Err(errno)
+    }
+}
+
+fn names(path: &str) -> Vec<String> {
+    let mut names = fs::read_dir(path)
+        .unwrap()
+        .map(|ent| ent.unwrap().file_name().into_string().unwrap())
+        .collect::<Vec<_>>();
+    names.sort();
+    names
+}
+
+fn errno(res: std::io::Result<()>) -> i32 {
+    res.err().unwrap().raw_os_error().unwrap()
+}
+
+fn symlink(target: &str, path: &str) {
+    let errno =
+        unsafe { path_symlink(target.as_ptr(), target.len(), 3, path.as_ptr(), path.len()) };
+    assert_eq!(errno, 0);
+
+    #[link(wasm_import_module = "wasi_snapshot_preview1")]
+    extern "C" {
+        fn path_symlink(
+            old_path_ptr: *const u8,
+            old_path_len: usize,
+            fd: i32,
+            new_path_ptr: *const u8,
+            new_path_len: usize,
+        ) -> u16;
+    }
+}
@@ -0,0 +1,30 @@
+// ```json
+// {
+//   "fs": { "foo": {} }
+// }
+// ```
+
+use std::fs;
+use std::io::Read;
+use std::os::wasi::io::{FromRawFd, IntoRawFd};
+
+#[link(wasm_import_module = "wasi_snapshot_preview1")]
+extern "C" {
+    fn fd_renumber(from: u32, to: u32) -> u16;
+}
+
+fn main() {
+    fs::write("foo/a", b"a").unwrap();
+    fs::write("foo/b", b"b").unwrap();
+    let a = fs::File::open("foo/a").unwrap().into_raw_fd();
+    let b = fs::File::open("foo/b").unwrap().into_raw_fd();
+
+    // `b` now refers to what `a` did, and `a` is closed.
+    assert_eq!(unsafe { fd_renumber(a as u32, b as u32) }, 0);
+    let mut file = unsafe { fs::File::from_raw_fd(b) };
+    let mut buf = String::new();
+    file.read_to_string(&mut buf).unwrap();
+    assert_eq!(buf, "a");
+    // EBADF
+    assert_eq!(unsafe { fd_renumber(a as u32, b as u32) }, 8);
+}
@@ -0,0 +1,89 @@
+// ```json
+// {
+//   "fs": { "foo": {} }
+// }
+// ```
+
+use std::fs;
+use std::io::{Read, Write};
+use std::os::wasi::io::{AsRawFd, FromRawFd};
+use std::time::SystemTime;
+
+#[link(wasm_import_module = "wasi_snapshot_preview1")]
+extern "C" {
+    // `fdstat` is laid out as a filetype, flags and then the two sets of rights.
+    fn fd_fdstat_get(fd: u32, fdstat: *mut [u64; 3]) -> u16;
+    fn fd_fdstat_set_rights(fd: u32, fs_rights_base: u64, fs_rights_inheriting: u64) -> u16;
+    fn path_open(
+        fd: u32,
+        dirflags: u32,
+        path_ptr: *const u8,
+        path_len: usize,
+        oflags: u32,
+        fs_rights_base: u64,
+        fs_rights_inheriting: u64,
+        fdflags: u32,
+        opened_fd: *mut u32,
+    ) -> u16;
+}
+
+// Rights
+const FD_READ: u64 = 1 << 1;
+const FD_WRITE: u64 = 1 << 6;
+const FD_FILESTAT_SET_TIMES: u64 = 1 << 23;
+
+fn main() {
+    fs::write("foo/bar", b"foo").unwrap();
+
+    // A file opened for reading can't be written to, and vice versa.
+    let mut file = fs::File::open("foo/bar").unwrap();
+    assert!(file.write_all(b"bar").is_err());
+    assert!(file.set_len(0).is_err());
+
+    let mut file = fs::OpenOptions::new().write(true).open("foo/bar").unwrap();
+    let mut buf = Vec::new();
+    assert!(file.read_to_end(&mut buf).is_err());
+    file.write_all(b"bar").unwrap();
+
+    assert_eq!(fs::read("foo/bar").unwrap(), b"bar");
+
+    // Nor can its times be set once the right to is gone.
+    let file = fs::File::open("foo/bar").unwrap();
+    let fd = file.as_raw_fd() as u32;
+    let mut fdstat = [0; 3];
+    assert_eq!(unsafe { fd_fdstat_get(fd, &mut fdstat) }, 0);
+    let rights = fdstat[1] & !FD_FILESTAT_SET_TIMES;
+    assert_eq!(unsafe { fd_fdstat_set_rights(fd, rights, fdstat[2]) }, 0);
+    let times = fs::FileTimes::new().set_modified(SystemTime::UNIX_EPOCH);
+    // ENOTCAPABLE
+    assert_eq!(
+        file.set_times(times).err().unwrap().raw_os_error(),
+        Some(76)
+    );
+
+    // A dir that doesn't pass on the right to write opens files without it, even when
+    // asked for every right, like libc does.
+    let dir = fs::File::open("foo").unwrap();
+    let dir_fd = dir.as_raw_fd() as u32;
+    assert_eq!(unsafe { fd_fdstat_get(dir_fd, &mut fdstat) }, 0);
+    let rights_inheriting = fdstat[2] & !FD_WRITE;
+    assert_eq!(
+        unsafe { fd_fdstat_set_rights(dir_fd, fdstat[1], rights_inheriting) },
+        0
+    );
+    let path = "bar";
+    let mut fd = 0;
+    let errno = unsafe { path_open(dir_fd, 0, path.as_ptr(), path.len(), 0, !0, !0, 0, &mut fd) };
+    assert_eq!(errno, 0);
+    assert_eq!(unsafe { fd_fdstat_get(fd, &mut fdstat) }, 0);
+    assert_eq!(fdstat[1] & (FD_READ | FD_WRITE), FD_READ);
+    // So it can be read but not written. EBADF
+    let mut file = unsafe { fs::File::from_raw_fd(fd as i32) };
+    let mut buf = Vec::new();
+    file.read_to_end(&mut buf).unwrap();
+    assert_eq!(buf, b"bar");
+    assert_eq!(
+        file.write_all(b"bar").err().unwrap().raw_os_error(),
+        Some(8)
+    );
+}
@@ -0,0 +1,30 @@
+// ```json
+// {
+//   "fs": { "foo": {} }
+// }
+// ```
+
+use std::fs;
+use std::io::{Read, Seek, SeekFrom};
+
+fn main() {
+    fs::write("foo/bar", b"header...trailer").unwrap();
+
+    // Relative to the end, like parsers looking for a trailer.
+    let mut file = fs::File::open("foo/bar").unwrap();
+    assert_eq!(file.seek(SeekFrom::End(-7)).unwrap(), 9);
+    let mut buf = String::new();
+    file.read_to_string(&mut buf).unwrap();
+    assert_eq!(buf, "trailer");
+
+    assert_eq!(file.seek(SeekFrom::Current(-10)).unwrap(), 6);
+    assert_eq!(file.seek(SeekFrom::Current(0)).unwrap(), 6);
+
+    // Seeking before the start fails and keeps the position.
+    assert!(file.seek(SeekFrom::Current(-7)).is_err());
+    assert!(file.seek(SeekFrom::End(-17)).is_err());
+    assert_eq!(file.seek(SeekFrom::Current(0)).unwrap(), 6);
+
+    // Past the end is fine.
+    assert_eq!(file.seek(SeekFrom::End(10)).unwrap(), 26);
+}
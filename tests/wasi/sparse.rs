@@ -0,0 +1,106 @@
+// ```json
+// {
+//   "fs": { "foo": {} }
+// }
+// ```
+
+use std::fs;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::os::wasi::fs::FileExt;
+use std::os::wasi::io::AsRawFd;
+
+#[link(wasm_import_module = "wasi_snapshot_preview1")]
+extern "C" {
+    fn fd_seek(fd: u32, offset: i64, whence: u8, newoffset: *mut u64) -> u16;
+}
+
+const GB: u64 = 1024 * 1024 * 1024;
+
+// Whences beyond WASI's, numbered like Linux's.
+const SEEK_DATA: u8 = 3;
+const SEEK_HOLE: u8 = 4;
+
+// Errnos
+const NXIO: u16 = 60;
+
+fn seek(file: &fs::File, offset: i64, whence: u8) -> Result<u64, u16> {
+    let mut pos = 0;
+    match unsafe { fd_seek(file.as_raw_fd() as u32, offset, whence, &mut pos) } {
+        0 => Ok(pos),
+        errno => Err(errno),
+    }
+}
+
+fn main() {
+    // Way more than the memory limit, so this only works if the holes aren't stored.
+    let mut file = fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .open("foo/file")
+        .unwrap();
+    file.write_all(b"head").unwrap();
+    file.set_len(4 * GB).unwrap();
+    assert_eq!(file.metadata().unwrap().len(), 4 * GB);
+
+    file.seek(SeekFrom::Start(4 * GB - 4)).unwrap();
+    file.write_all(b"tail").unwrap();
+    file.seek(SeekFrom::Start(2 * GB)).unwrap();
+    file.write_all(b"middle").unwrap();
+
+    let mut buf = [1; 4];
+    file.seek(SeekFrom::Start(0)).unwrap();
+    file.read_exact(&mut buf).unwrap();
+    assert_eq!(&buf, b"head");
+
+    let mut buf = [1; 16];
+    file.seek(SeekFrom::Start(GB)).unwrap();
+    file.read_exact(&mut buf).unwrap();
+    assert!(buf.iter().all(|b| *b == 0));
+
+    let mut buf = [1; 10];
+    file.seek(SeekFrom::Start(2 * GB - 2)).unwrap();
+    file.read_exact(&mut buf).unwrap();
+    assert_eq!(&buf, b"\0\0middle\0\0");
+
+    let mut buf = [1; 4];
+    file.seek(SeekFrom::Start(4 * GB - 4)).unwrap();
+    file.read_exact(&mut buf).unwrap();
+    assert_eq!(&buf, b"tail");
+
+    // Shrinking drops the data past the new end.
+    file.set_len(2 * GB + 2).unwrap();
+    file.set_len(3 * GB).unwrap();
+    let mut buf = [1; 6];
+    file.seek(SeekFrom::Start(2 * GB)).unwrap();
+    file.read_exact(&mut buf).unwrap();
+    assert_eq!(&buf, b"mi\0\0\0\0");
+
+    // Data at 4096..4101 and 8192..8197 of 12288 bytes, with holes around both.
+    let file = fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .open("foo/holes")
+        .unwrap();
+    file.write_at(b"data1", 4096).unwrap();
+    file.write_at(b"data2", 8192).unwrap();
+    file.set_len(12288).unwrap();
+
+    assert_eq!(seek(&file, 0, SEEK_DATA), Ok(4096));
+    assert_eq!(seek(&file, 4098, SEEK_DATA), Ok(4098));
+    assert_eq!(seek(&file, 4101, SEEK_DATA), Ok(8192));
+    assert_eq!(seek(&file, 0, SEEK_HOLE), Ok(0));
+    assert_eq!(seek(&file, 4096, SEEK_HOLE), Ok(4101));
+    assert_eq!(seek(&file, 8194, SEEK_HOLE), Ok(8197));
+    assert_eq!(seek(&file, 12287, SEEK_HOLE), Ok(12287));
+    // The seek moves the position too.
+    assert_eq!(seek(&file, 0, SEEK_DATA), Ok(4096));
+    assert_eq!((&file).stream_position().unwrap(), 4096);
+
+    // Only the trailing hole is left, and nothing is past the end.
+    assert_eq!(seek(&file, 8197, SEEK_DATA), Err(NXIO));
+    assert_eq!(seek(&file, 12288, SEEK_DATA), Err(NXIO));
+    assert_eq!(seek(&file, 12288, SEEK_HOLE), Err(NXIO));
+    assert_eq!(seek(&file, 20000, SEEK_HOLE), Err(NXIO));
+}
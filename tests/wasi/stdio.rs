@@ -0,0 +1,23 @@
+// Run with stdin fed by the host, see nodeWASI.test.mjs.
+
+use std::io::{Read, Write};
+
+fn main() {
+    let mut input = String::new();
+    std::io::stdin().read_to_string(&mut input).unwrap();
+    assert_eq!(input, "hello world");
+
+    // More than fits in the pipes, so this has to wait for the host to drain them. The
+    // host can't wait on stdout first, since stderr fills up before anything is written to
+    // it.
+    let mut stderr = std::io::stderr();
+    for _ in 0..10000 {
+        stderr.write_all(input.as_bytes()).unwrap();
+    }
+    let mut stdout = std::io::stdout();
+    for _ in 0..10000 {
+        stdout.write_all(input.as_bytes()).unwrap();
+    }
+    stdout.flush().unwrap();
+    eprint!("done");
+}
@@ -0,0 +1,72 @@
+// ```json
+// {
+//   "fs": { "foo": { "bar": {} } }
+// }
+// ```
+
+use std::fs;
+use std::io::{Read, Write};
+
+fn main() {
+    fs::File::create("foo/bar/file")
+        .unwrap()
+        .write_all(b"foo bar baz")
+        .unwrap();
+
+    // Relative targets resolve against the directory holding the link.
+    symlink("bar/file", "foo/file_link");
+    assert_eq!(
+        fs::read_link("foo/file_link").unwrap().to_str(),
+        Some("bar/file")
+    );
+    assert!(fs::symlink_metadata("foo/file_link")
+        .unwrap()
+        .file_type()
+        .is_symlink());
+    assert!(fs::metadata("foo/file_link").unwrap().is_file());
+    {
+        let mut test = String::new();
+        fs::File::open("foo/file_link")
+            .unwrap()
+            .read_to_string(&mut test)
+            .unwrap();
+        assert_eq!(&test, "foo bar baz");
+    }
+
+    // Links to dirs are followed in the middle of a path.
+    symlink("../foo/bar", "foo/dir_link");
+    assert!(fs::metadata("foo/dir_link/file").unwrap().is_file());
+
+    // Existing names can't be reused.
+    assert_ne!(raw_symlink("bar/file", "foo/file_link"), 0);
+
+    // Loops are reported instead of being followed forever.
+    symlink("loop_b", "foo/loop_a");
+    symlink("loop_a", "foo/loop_b");
+    let err = fs::File::open("foo/loop_a").err().unwrap();
+    // ELOOP
+    assert_eq!(err.raw_os_error(), Some(32));
+
+    // Removing a link leaves its target alone.
+    assert!(fs::remove_file("foo/file_link").is_ok());
+    assert!(fs::metadata("foo/bar/file").unwrap().is_file());
+}
+
+fn symlink(target: &str, path: &str) {
+    assert_eq!(raw_symlink(target, path), 0);
+}
+
+fn raw_symlink(target: &str, path: &str) -> u16 {
+    return unsafe { path_symlink(target.as_ptr(), target.len(), 3, path.as_ptr(), path.len()) };
+
+    #[link(wasm_import_module = "wasi_snapshot_preview1")]
+    extern "C" {
+        fn path_symlink(
+            old_path_ptr: *const u8,
+            old_path_len: usize,
+            fd: i32,
+            new_path_ptr: *const u8,
+            new_path_len: usize,
+        ) -> u16;
+    }
+}
@@ -0,0 +1,46 @@
+// ```json
+// {
+//   "fs": { "foo": {} }
+// }
+// ```
+
+use std::fs;
+use std::io::Write;
+use std::time::{Duration, SystemTime};
+
+fn main() {
+    let before = SystemTime::now() - Duration::from_secs(1);
+    fs::File::create("foo/file").unwrap();
+    let meta = fs::metadata("foo/file").unwrap();
+    assert!(meta.modified().unwrap() > before);
+    assert!(meta.accessed().unwrap() > before);
+
+    // Setting the times explicitly.
+    let past = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000);
+    fs::File::options()
+        .write(true)
+        .open("foo/file")
+        .unwrap()
+        .set_times(fs::FileTimes::new().set_accessed(past).set_modified(past))
+        .unwrap();
+    let meta = fs::metadata("foo/file").unwrap();
+    assert_eq!(meta.modified().unwrap(), past);
+    assert_eq!(meta.accessed().unwrap(), past);
+
+    // Writing bumps the modify time.
+    fs::OpenOptions::new()
+        .append(true)
+        .open("foo/file")
+        .unwrap()
+        .write_all(b"foo")
+        .unwrap();
+    assert!(fs::metadata("foo/file").unwrap().modified().unwrap() > past);
+
+    // Renaming keeps the modify time.
+    let modified = fs::metadata("foo/file").unwrap().modified().unwrap();
+    assert!(fs::rename("foo/file", "foo/renamed").is_ok());
+    assert_eq!(
+        fs::metadata("foo/renamed").unwrap().modified().unwrap(),
+        modified
+    );
+}
@@ -0,0 +1,10 @@
+// Run with `gone` mounted from a host file that's removed before this starts, see
+// nodeWASI.test.mjs.
+
+use std::fs;
+
+fn main() {
+    // Its contents can't be fetched anymore, but truncating doesn't need them.
+    fs::write("gone", b"new").unwrap();
+    assert_eq!(fs::read("gone").unwrap(), b"new");
+}